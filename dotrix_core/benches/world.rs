@@ -0,0 +1,105 @@
+use criterion::{ black_box, criterion_group, criterion_main, BatchSize, Criterion };
+
+use dotrix_core::{ ecs::Entity, services::World };
+
+/// Number of tag types, entities with all combinations of tags form 2^TAGS archetypes
+const TAGS: usize = 8;
+/// Number of entities in each archetype
+const ENTITIES: usize = 10;
+
+struct Position(f32);
+struct Velocity(f32);
+struct Tag<const N: usize>;
+
+fn tag<const N: usize>(world: &mut World, entity: Entity, mask: usize) {
+    if mask & (1 << N) != 0 {
+        world.insert(entity, Tag::<N>);
+    }
+}
+
+/// Creates the world with 2^TAGS archetypes
+fn world() -> World {
+    let mut world = World::new();
+    for mask in (0..1 << TAGS).rev() {
+        let entities = world.spawn((0..ENTITIES).map(|i| (Position(i as f32), Velocity(1.0))));
+        for entity in entities {
+            tag::<0>(&mut world, entity, mask);
+            tag::<1>(&mut world, entity, mask);
+            tag::<2>(&mut world, entity, mask);
+            tag::<3>(&mut world, entity, mask);
+            tag::<4>(&mut world, entity, mask);
+            tag::<5>(&mut world, entity, mask);
+            tag::<6>(&mut world, entity, mask);
+            tag::<7>(&mut world, entity, mask);
+        }
+    }
+    world
+}
+
+fn spawn(c: &mut Criterion) {
+    let mut world = world();
+    c.bench_function("spawn 1000 entities among 256 archetypes", |b| {
+        b.iter(|| {
+            for i in 0..1000 {
+                black_box(world.spawn(Some((Velocity(i as f32), Tag::<7>))));
+            }
+        })
+    });
+}
+
+fn query(c: &mut Criterion) {
+    let world = world();
+    c.bench_function("query 256 archetypes", |b| {
+        b.iter(|| {
+            for (position, velocity) in world.query::<(&mut Position, &Velocity)>() {
+                position.0 += velocity.0;
+            }
+        })
+    });
+}
+
+/// Spawns an entity of `(Position, Tag<N>)` archetype for each of the tags
+macro_rules! spawn_tagged {
+    ($world:expr; $($n:literal)*) => {
+        $( black_box($world.spawn(Some((Position(0.0), Tag::<$n>)))); )*
+    };
+}
+
+/// Spawns into archetypes, that already exist among many others, so every spawn looks up the
+/// archetype by its signature
+fn archetype_lookup(c: &mut Criterion) {
+    let mut world = world();
+    spawn_tagged!(world; 0 1 2 3 4 5 6 7);
+    c.bench_function("spawn into each of 8 of 264 archetypes", |b| {
+        b.iter(|| {
+            for _ in 0..100 {
+                spawn_tagged!(world; 0 1 2 3 4 5 6 7);
+            }
+        })
+    });
+}
+
+/// Compares the first query of a kind, that matches all archetypes of the world, with repeated
+/// queries, that reuse the cached matches
+fn query_matching(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query 16 of 256 archetypes");
+    group.bench_function("cache miss", |b| {
+        b.iter_batched_ref(
+            world,
+            |world| {
+                black_box(world.query::<(&Position, &Tag<0>, &Tag<1>, &Tag<2>, &Tag<3>)>().count())
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    let world = world();
+    group.bench_function("cache hit", |b| {
+        b.iter(|| {
+            black_box(world.query::<(&Position, &Tag<0>, &Tag<1>, &Tag<2>, &Tag<3>)>().count())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, spawn, query, archetype_lookup, query_matching);
+criterion_main!(benches);
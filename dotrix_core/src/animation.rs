@@ -1,10 +1,9 @@
 use std::time::{Duration};
-use dotrix_math::{SquareMatrix, Mat4};
 
 use crate::{
     assets::{Animation, Id},
     components::Model,
-    ecs::{Const},
+    ecs::{Const, Mut},
     services::{Assets, Frame, World},
 };
 
@@ -103,9 +102,10 @@ impl Animator {
 
 }
 
-pub fn skeletal_animation(frame: Const<Frame>, world: Const<World>, assets: Const<Assets>) {
+pub fn skeletal_animation(frame: Const<Frame>, world: Mut<World>, assets: Const<Assets>) {
     for (model, animator) in world.query::<(&mut Model, &mut Animator)>() {
-        let global_transform = Mat4::identity(); // model.transform.matrix();
+        // joints are posed in the model space, `GlobalTransform` of the entity is applied on top
+        // of them by the skinned shader (see `Skin::transform`)
         if let Some(skin) = assets.get(model.skin) {
 
             let mut local_transforms = None;
@@ -117,7 +117,7 @@ pub fn skeletal_animation(frame: Const<Frame>, world: Const<World>, assets: Cons
             }
 
             if let Some(pose) = model.pose.as_mut() {
-                skin.transform(pose, &global_transform, local_transforms);
+                skin.transform(pose, local_transforms);
             }
         }
     }
@@ -1,3 +1,4 @@
+mod headless;
 pub mod services;
 
 use std::{
@@ -15,12 +16,15 @@ use crate::{
     ecs::System,
     frame::Frame,
     input::Input,
+    profiler::Profiler,
     renderer::Renderer,
     scheduler::Scheduler,
 };
 
 use services::Services;
 
+pub use headless::{ Clock, Headless, ManualClock, SystemClock };
+
 pub struct Application {
     name: &'static str,
     scheduler: Scheduler,
@@ -31,10 +35,13 @@ pub struct Application {
 
 impl Application {
     pub fn new(name: &'static str) -> Self {
+        let mut services = Services::new();
+        services.add(AppControl::new());
+
         Self {
             name,
             scheduler: Scheduler::new(),
-            services: Services::new(),
+            services,
             clear_color: [0.1, 0.2, 0.3, 1.0],
             fullscreen: false,
         }
@@ -53,11 +60,39 @@ impl Application {
         self.services.add(service);
     }
 
+    /// Removes the service, returns it if it was added
+    pub fn remove_service<T: Service>(&mut self) -> Option<T> {
+        self.services.remove::<T>()
+    }
+
     pub fn service<T: Service>(&mut self) -> &mut T
     {
         self.services.get_mut::<T>().expect("Application services does not exist")
     }
 
+    /// Returns true if the service of the type was added
+    pub fn has_service<T: Service>(&self) -> bool {
+        self.services.get::<T>().is_some()
+    }
+
+    /// Adds the service if the application has no service of the type yet, so extensions do
+    /// not override services configured by the user
+    pub fn add_default_service<T: Service>(&mut self, service: T) {
+        if !self.has_service::<T>() {
+            self.services.add(service);
+        }
+    }
+
+    /// Adds systems and services of the extension
+    pub fn add_extension<E: Extension>(&mut self, extension: E) {
+        extension.add_to(self);
+    }
+
+    /// Turns the application into the headless one, running without a window
+    pub fn headless(self) -> Headless {
+        Headless::new(self.scheduler, self.services)
+    }
+
     /// Run the application
     pub fn run(self) {
         let event_loop = EventLoop::new();
@@ -74,6 +109,44 @@ impl Application {
 pub trait Service: Send + Sync + 'static {}
 impl<T: Send + Sync + 'static> Service for T {}
 
+/// Set of systems and services, that can be added to the application at once
+///
+/// Extensions should add services with `Application::add_default_service`, so services
+/// configured by the user are not overridden regardless of the order of calls.
+pub trait Extension {
+    fn add_to(self, app: &mut Application);
+}
+
+/// Service controlling the application run cycle
+pub struct AppControl {
+    exit: bool,
+}
+
+impl AppControl {
+    pub fn new() -> Self {
+        Self {
+            exit: false,
+        }
+    }
+
+    /// Requests the application to exit after the current frame. `RunLevel::Shutdown` systems
+    /// run before the application terminates
+    pub fn exit(&mut self) {
+        self.exit = true;
+    }
+
+    /// Returns true if the exit was requested
+    pub fn exit_requested(&self) -> bool {
+        self.exit
+    }
+}
+
+impl Default for AppControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Application run cycle
 fn run(
     event_loop: EventLoop<()>,
@@ -94,6 +167,7 @@ fn run(
         (local_pool, spawner)
     };
     let mut last_update_inst = Instant::now();
+    let mut last_frame_inst = Instant::now();
 
     services.add(Renderer::new(device, queue, surface, window, clear_color));
 
@@ -138,9 +212,18 @@ fn run(
                 *control_flow = ControlFlow::Exit;
             },
             Event::RedrawRequested(_) => {
+                let now = Instant::now();
+                let delta = now - last_frame_inst;
+                last_frame_inst = now;
+
                 if let Some(frame) = services.get_mut::<Frame>() {
                     frame.next();
                 }
+                if let Some(profiler) = services.get_mut::<Profiler>() {
+                    profiler.next_frame();
+                }
+                scheduler.run_transitions(&mut services);
+                scheduler.run_fixed(&mut services, delta);
                 scheduler.run_standard(&mut services);
                 if let Some(renderer) = services.get_mut::<Renderer>() {
                     renderer.next_frame();
@@ -152,6 +235,12 @@ fn run(
                 if let Some(input) = services.get_mut::<Input>() {
                     input.reset();
                 }
+                if services.get::<AppControl>().map(|c| c.exit_requested()).unwrap_or(false) {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            Event::LoopDestroyed => {
+                scheduler.run_shutdown(&mut services);
             }
             _ => {}
         }
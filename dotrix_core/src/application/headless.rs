@@ -0,0 +1,372 @@
+use std::time::{ Duration, Instant };
+
+use crate::{
+    assets::Assets,
+    frame::Frame,
+    input::Input,
+    profiler::Profiler,
+    scheduler::Scheduler,
+};
+
+use super::{ AppControl, Service, services::Services };
+
+/// Default duration of a frame of the headless application
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Source of time for the headless application
+pub trait Clock: Send + 'static {
+    /// Returns current time
+    fn now(&self) -> Instant;
+    /// Moves the clock to the start of the next frame, `frame_time` after the previous one
+    fn advance(&mut self, frame_time: Duration);
+}
+
+/// Real time clock, waiting for the next frame if the previous one was faster than frame time
+#[derive(Default)]
+pub struct SystemClock {
+    last: Option<Instant>,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn advance(&mut self, frame_time: Duration) {
+        if let Some(last) = self.last {
+            let elapsed = last.elapsed();
+            if elapsed < frame_time {
+                std::thread::sleep(frame_time - elapsed);
+            }
+        }
+        self.last = Some(Instant::now());
+    }
+}
+
+/// Simulated clock, advancing exactly by the frame time on each frame without waiting
+pub struct ManualClock {
+    now: Instant,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            now: Instant::now(),
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now
+    }
+
+    fn advance(&mut self, frame_time: Duration) {
+        self.now += frame_time;
+    }
+}
+
+/// Application running systems without a window, `Renderer` and render systems
+///
+/// Frames are executed only on `step` and `step_for` calls, so the application can be used on a
+/// dedicated server or to test gameplay systems. Startup systems run before the first frame.
+/// Shutdown systems run after the frame, in which `AppControl::exit` was called, or on explicit
+/// `shutdown` call. Frames are not executed after that.
+pub struct Headless {
+    scheduler: Scheduler,
+    services: Services,
+    clock: Box<dyn Clock>,
+    frame_time: Duration,
+    last_frame: Option<Instant>,
+    running: bool,
+}
+
+impl Headless {
+    pub(crate) fn new(scheduler: Scheduler, services: Services) -> Self {
+        Self {
+            scheduler,
+            services,
+            clock: Box::new(SystemClock::default()),
+            frame_time: FRAME_TIME,
+            last_frame: None,
+            running: true,
+        }
+    }
+
+    /// Replaces the real time clock
+    pub fn with_clock(mut self, clock: impl Clock) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Sets duration of a frame, 1/60 of a second by default
+    pub fn with_frame_time(mut self, frame_time: Duration) -> Self {
+        self.frame_time = frame_time;
+        self
+    }
+
+    /// Runs the number of frames, returns number of executed frames, that could be less if the
+    /// application exits
+    pub fn step(&mut self, frames: usize) -> usize {
+        let mut executed = 0;
+        while executed < frames && self.running {
+            self.frame();
+            executed += 1;
+        }
+        executed
+    }
+
+    /// Runs frames until the duration passes on the clock or the application exits, returns
+    /// number of executed frames
+    pub fn step_for(&mut self, duration: Duration) -> usize {
+        if !self.running {
+            return 0;
+        }
+        self.start();
+        let start = self.clock.now();
+        let mut frames = 0;
+        while self.running && self.clock.now().saturating_duration_since(start) < duration {
+            self.frame();
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Returns false if the application has exited
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Runs shutdown systems, if the application has not exited yet
+    pub fn shutdown(&mut self) {
+        if self.running {
+            self.running = false;
+            self.scheduler.run_shutdown(&mut self.services);
+        }
+    }
+
+    pub fn service<T: Service>(&self) -> Option<&T> {
+        self.services.get::<T>()
+    }
+
+    pub fn service_mut<T: Service>(&mut self) -> Option<&mut T> {
+        self.services.get_mut::<T>()
+    }
+
+    /// Replaces the service with the new instance or adds it, returns the previous instance.
+    /// Systems can do the same with the `ServiceCommands` accessor
+    pub fn replace_service<T: Service>(&mut self, service: T) -> Option<T> {
+        self.services.replace(service)
+    }
+
+    /// Removes the service, returns it if it existed
+    pub fn remove_service<T: Service>(&mut self) -> Option<T> {
+        self.services.remove::<T>()
+    }
+
+    /// Runs startup systems if they were not executed yet, returns the time of the last frame
+    fn start(&mut self) -> Instant {
+        if let Some(last_frame) = self.last_frame {
+            return last_frame;
+        }
+        self.scheduler.run_startup(&mut self.services);
+        let now = self.clock.now();
+        self.last_frame = Some(now);
+        now
+    }
+
+    fn frame(&mut self) {
+        let last_frame = self.start();
+        self.clock.advance(self.frame_time);
+        let now = self.clock.now();
+        let delta = now.saturating_duration_since(last_frame);
+        self.last_frame = Some(now);
+
+        if let Some(assets) = self.services.get_mut::<Assets>() {
+            assets.fetch();
+        }
+        if let Some(frame) = self.services.get_mut::<Frame>() {
+            frame.next_at(now);
+        }
+        if let Some(profiler) = self.services.get_mut::<Profiler>() {
+            profiler.next_frame();
+        }
+        self.scheduler.run_transitions(&mut self.services);
+        self.scheduler.run_fixed(&mut self.services, delta);
+        self.scheduler.run_standard(&mut self.services);
+        if let Some(input) = self.services.get_mut::<Input>() {
+            input.reset();
+        }
+        if self.services.get::<AppControl>().map(|c| c.exit_requested()).unwrap_or(false) {
+            self.shutdown();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        application::{ Application, Extension },
+        ecs::{ Const, Mut, RunLevel, ServiceCommands, System },
+        services::{ AppControl, Frame, World },
+    };
+    use super::ManualClock;
+
+    struct Position(u32);
+
+    struct Ticks(u32);
+
+    fn spawn(mut world: Mut<World>) {
+        world.spawn(Some((Position(0),)));
+    }
+
+    fn walk(world: Mut<World>) {
+        for (position,) in world.query::<(&mut Position,)>() {
+            position.0 += 1;
+        }
+    }
+
+    fn tick(mut ticks: Mut<Ticks>, frame: Const<Frame>) {
+        assert_eq!(frame.fixed_delta(), Duration::from_millis(100));
+        ticks.0 += 1;
+    }
+
+    fn application() -> Application {
+        let mut app = Application::new("Headless");
+        app.add_service(World::new());
+        app.add_service(Frame::new());
+        app.add_service(Ticks(0));
+        app.add_system(System::from(spawn).with(RunLevel::Startup));
+        app.add_system(System::from(walk));
+        app.add_system(System::from(tick).with(RunLevel::Fixed(10)));
+        app
+    }
+
+    #[test]
+    fn step() {
+        let mut app = application().headless().with_clock(ManualClock::new());
+        app.step(3);
+
+        let world = app.service::<World>().unwrap();
+        let positions = world.query::<(&Position,)>().map(|(p,)| p.0).collect::<Vec<_>>();
+        assert_eq!(positions, vec![3]);
+    }
+
+    /// Extension adding walking entities, as a user crate would do
+    struct Walking;
+
+    impl Extension for Walking {
+        fn add_to(self, app: &mut Application) {
+            app.add_default_service(World::new());
+            app.add_default_service(Ticks(10));
+            app.add_system(System::from(spawn).with(RunLevel::Startup));
+            app.add_system(System::from(walk));
+        }
+    }
+
+    #[test]
+    fn extension() {
+        let mut app = Application::new("Extension");
+        app.add_service(Ticks(0));
+        app.add_extension(Walking);
+        assert!(app.has_service::<World>());
+
+        let mut app = app.headless().with_clock(ManualClock::new());
+        app.step(2);
+        assert_eq!(app.service::<Ticks>().unwrap().0, 0, "User service must not be overridden");
+
+        let world = app.service::<World>().unwrap();
+        let positions = world.query::<(&Position,)>().map(|(p,)| p.0).collect::<Vec<_>>();
+        assert_eq!(positions, vec![2]);
+    }
+
+    #[derive(Default)]
+    struct Saved(bool);
+
+    fn quit(mut control: Mut<AppControl>, ticks: Const<Ticks>) {
+        if ticks.0 == 2 {
+            control.exit();
+        }
+    }
+
+    fn save(mut saved: Mut<Saved>) {
+        assert!(!saved.0);
+        saved.0 = true;
+    }
+
+    #[test]
+    fn shutdown() {
+        let mut app = application();
+        app.add_service(Saved::default());
+        app.add_system(System::from(quit));
+        app.add_system(System::from(save).with(RunLevel::Shutdown));
+
+        let mut app = app.headless()
+            .with_clock(ManualClock::new())
+            .with_frame_time(Duration::from_millis(100));
+
+        assert_eq!(app.step(10), 2);
+        assert!(!app.is_running());
+        assert!(app.service::<Saved>().unwrap().0);
+        assert_eq!(app.step_for(Duration::from_secs(1)), 0);
+        app.shutdown();
+    }
+
+    struct Mapper(&'static str);
+
+    #[derive(Default)]
+    struct MenuFrames(u32);
+
+    fn open_menu(mapper: Const<Mapper>, mut services: ServiceCommands) {
+        if mapper.0 == "game" {
+            services.replace(Mapper("menu"));
+            services.remove::<Saved>();
+        }
+    }
+
+    fn count_menu_frames(mapper: Const<Mapper>, mut frames: Mut<MenuFrames>) {
+        if mapper.0 == "menu" {
+            frames.0 += 1;
+        }
+    }
+
+    #[test]
+    fn swap_services() {
+        let mut app = application();
+        app.add_service(Mapper("game"));
+        app.add_service(Saved::default());
+        app.add_service(MenuFrames::default());
+        app.add_system(System::from(open_menu));
+        app.add_system(System::from(count_menu_frames));
+
+        let mut app = app.headless().with_clock(ManualClock::new());
+        app.step(3);
+        assert_eq!(app.service::<Mapper>().unwrap().0, "menu");
+        assert_eq!(app.service::<MenuFrames>().unwrap().0, 2);
+        assert!(app.service::<Saved>().is_none());
+
+        assert_eq!(app.replace_service(Mapper("game")).unwrap().0, "menu");
+        assert!(app.remove_service::<Ticks>().is_some());
+        assert!(app.service::<Ticks>().is_none());
+    }
+
+    #[test]
+    fn step_for() {
+        let mut app = application()
+            .headless()
+            .with_clock(ManualClock::new())
+            .with_frame_time(Duration::from_millis(50));
+
+        assert_eq!(app.step_for(Duration::from_secs(1)), 20);
+        assert_eq!(app.service::<Ticks>().unwrap().0, 10);
+        assert_eq!(app.service::<Frame>().unwrap().time(), Duration::from_millis(950));
+    }
+}
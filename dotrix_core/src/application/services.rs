@@ -1,12 +1,19 @@
 use std::{
-    any::TypeId,
+    any::{Any, TypeId},
+    cell::UnsafeCell,
     collections::HashMap,
 };
 
 use super::Service;
 
+/// Storage cell of a service. Concurrent access to services is controlled by the scheduler, that
+/// never runs systems with conflicting access in parallel
+struct Cell<T>(UnsafeCell<T>);
+
+unsafe impl<T: Service> Sync for Cell<T> {}
+
 pub struct Services {
-    storage: HashMap<TypeId, Box<dyn std::any::Any>>,
+    storage: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
 }
 
 impl Services {
@@ -18,19 +25,40 @@ impl Services {
     }
 
     pub fn add<T: Service>(&mut self, service: T) {
-        self.storage.insert(TypeId::of::<T>(), Box::new(service));
+        self.replace(service);
     }
 
     pub fn get<T: Service>(&self) -> Option<&T> {
         self.storage
             .get(&TypeId::of::<T>())
-            .map(|srv| srv.downcast_ref::<T>().unwrap())
+            .map(|srv| unsafe { &*srv.downcast_ref::<Cell<T>>().unwrap().0.get() })
     }
 
     pub fn get_mut<T: Service>(&mut self) -> Option<&mut T> {
         self.storage
             .get_mut(&TypeId::of::<T>())
-            .map(|srv| srv.downcast_mut::<T>().unwrap())
+            .map(|srv| srv.downcast_mut::<Cell<T>>().unwrap().0.get_mut())
+    }
+
+    /// Removes the service, returns it if it existed
+    pub fn remove<T: Service>(&mut self) -> Option<T> {
+        self.storage
+            .remove(&TypeId::of::<T>())
+            .map(|srv| srv.downcast::<Cell<T>>().unwrap().0.into_inner())
+    }
+
+    /// Replaces the service with the new instance, returns the previous one if it existed
+    pub fn replace<T: Service>(&mut self, service: T) -> Option<T> {
+        self.storage
+            .insert(TypeId::of::<T>(), Box::new(Cell(UnsafeCell::new(service))))
+            .map(|srv| srv.downcast::<Cell<T>>().unwrap().0.into_inner())
+    }
+
+    /// Returns a pointer to the service for systems accessing it mutably
+    pub(crate) fn get_ptr<T: Service>(&self) -> Option<*mut T> {
+        self.storage
+            .get(&TypeId::of::<T>())
+            .map(|srv| srv.downcast_ref::<Cell<T>>().unwrap().0.get())
     }
 }
 
@@ -39,3 +67,21 @@ impl Default for Services {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Services;
+
+    struct Mapper(&'static str);
+
+    #[test]
+    fn replace_and_remove() {
+        let mut services = Services::new();
+        assert!(services.replace(Mapper("game")).is_none());
+        assert_eq!(services.replace(Mapper("menu")).unwrap().0, "game");
+        assert_eq!(services.get::<Mapper>().unwrap().0, "menu");
+        assert_eq!(services.remove::<Mapper>().unwrap().0, "menu");
+        assert!(services.get::<Mapper>().is_none());
+        assert!(services.remove::<Mapper>().is_none());
+    }
+}
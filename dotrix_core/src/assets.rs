@@ -1,39 +1,64 @@
 mod animation;
+mod handle;
 mod id;
 mod loader;
 mod load_gltf;
+mod map;
 mod mesh;
+mod pack;
 mod skin;
 mod resource;
 mod texture;
+mod usage;
+mod vfs;
+mod watcher;
 
+pub use handle::*;
 pub use id::*;
 pub use loader::*;
+pub use load_gltf::GltfImporter;
 pub use animation::Animation;
 pub use mesh::*;
+pub use pack::{Pack, PackBuilder};
 pub use skin::{Skin, Pose}; // TODO: consider moving of Pose to some shared place
 pub use resource::*;
 pub use texture::*;
+pub use usage::*;
+pub use vfs::{Directory, Mount, Vfs};
 
 use std::{
-    collections::HashMap,
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet},
+    path::PathBuf,
     sync::{Arc, mpsc, Mutex},
+    time::{Duration, Instant},
     vec::Vec,
 };
 
+use map::{AssetMap, Map};
+use watcher::Watcher;
+
 const THREADS_COUNT: usize = 4;
 
+/// Container of assets of any `'static + Send + Sync` type, imported from files by importers
+/// registered for file extensions
+///
+/// Files are read through the virtual filesystem, so they could be imported from mounted
+/// directories and packs as well as from the native filesystem.
 pub struct Assets {
     registry: HashMap<String, RawId>,
-    resources: HashMap<Id<Resource>, Resource>,
-    animations: HashMap<Id<Animation>, Animation>,
-    textures: HashMap<Id<Texture>, Texture>,
-    meshes: HashMap<Id<Mesh>, Mesh>,
-    skins: HashMap<Id<Skin>, Skin>,
+    maps: HashMap<TypeId, Box<dyn AssetMap>>,
+    importers: HashMap<String, Arc<dyn AssetImporter>>,
     loaders: Vec<Loader>,
     sender: mpsc::Sender<Request>,
     receiver: mpsc::Receiver<Response>,
     id_generator: RawId,
+    watcher: Option<Watcher>,
+    reloads: HashSet<Id<Resource>>,
+    revision: u64,
+    /// Reference counters of assets managed by handles
+    handles: HashMap<(TypeId, RawId), Arc<Counter>>,
+    vfs: Arc<Vfs>,
 }
 
 impl Assets {
@@ -51,45 +76,164 @@ impl Assets {
             loaders.push(Loader::new(id, Arc::clone(&thread_rx), Arc::clone(&thread_tx)));
         }
 
-        Self {
+        let mut assets = Self {
             registry: HashMap::new(),
-            resources: HashMap::new(),
-            animations: HashMap::new(),
-            textures: HashMap::new(),
-            meshes: HashMap::new(),
-            skins: HashMap::new(),
+            maps: HashMap::new(),
+            importers: HashMap::new(),
             loaders,
             sender,
             receiver,
             id_generator: 1,
+            watcher: None,
+            reloads: HashSet::new(),
+            revision: 0,
+            handles: HashMap::new(),
+            vfs: Arc::new(Vfs::new()),
+        };
+
+        assets.track_memory_usage::<Resource>();
+        assets.track_memory_usage::<Animation>();
+        assets.track_memory_usage::<Texture>();
+        assets.track_memory_usage::<Mesh>();
+        assets.track_memory_usage::<Skin>();
+
+        assets.typed_map_mut::<Texture>().unload_with(Texture::unload);
+        assets.typed_map_mut::<Mesh>().unload_with(Mesh::unload);
+
+        assets.add_importer(&["png", "jpg", "jpeg", "bmp"], ImageImporter);
+        assets.add_importer(&["gltf", "gltb"], GltfImporter);
+
+        assets
+    }
+
+    /// Registers the importer for files with the extensions, replacing importers registered
+    /// for them before
+    pub fn add_importer<I>(&mut self, extensions: &[&str], importer: I)
+    where
+        I: AssetImporter + 'static,
+    {
+        let importer: Arc<dyn AssetImporter> = Arc::new(importer);
+        for extension in extensions.iter() {
+            self.importers.insert(extension.to_lowercase(), Arc::clone(&importer));
         }
     }
 
+    /// Counts memory usage of assets of the type in `stats` using the `MemoryUsage` trait,
+    /// otherwise only inline size of the assets is counted
+    pub fn track_memory_usage<T>(&mut self)
+    where
+        T: MemoryUsage + Any + Send + Sync,
+    {
+        self.typed_map_mut::<T>().track_memory_usage();
+    }
+
+    /// Mounts the directory or the pack to the virtual filesystem. Files of later mounts shadow
+    /// files of the earlier ones under the same paths
+    pub fn mount<M: Mount + 'static>(&mut self, mount: M) {
+        self.vfs.mount(mount);
+    }
+
     /// imports an asset file to the container
     pub fn import(&mut self, path_str: &str) -> Id<Resource> {
         let path = std::path::Path::new(path_str);
         let name = path.file_stem().map(|n| n.to_str().unwrap()).unwrap();
         let resource = Resource::new(name.to_string(), path_str.to_string());
         let id = self.store::<Resource>(resource, name);
-        // TODO: start loading in separate thread
-        let task = Task { path: path.to_path_buf(), name: name.to_string() };
-        self.sender.send(Request::Import(task)).unwrap();
+        self.request_import(id, PathBuf::from(path_str), None);
+        id
+    }
+
+    /// Imports content of a file with the extension from memory. Relative paths of files
+    /// referenced by it are resolved from the root of the virtual filesystem
+    pub fn import_bytes(&mut self, name: &str, extension: &str, data: Vec<u8>) -> Id<Resource> {
+        let resource = Resource::new(name.to_string(), String::new());
+        let id = self.store::<Resource>(resource, name);
+        let path = PathBuf::from(format!("{}.{}", name, extension));
+        self.request_import(id, path, Some(data));
         id
     }
 
+    /// Enables hot reload of imported files, if the interval is set, or disables it otherwise
+    ///
+    /// When enabled, `fetch` checks modification time of imported files each interval and
+    /// imports changed files again. Reimported assets replace the old ones under the same ids,
+    /// so their `revision_of` is incremented, and `revision` is incremented when the reimport is
+    /// finished. Files modified before the first check are not reloaded.
+    pub fn set_hot_reload(&mut self, interval: Option<Duration>) {
+        self.watcher = interval.map(Watcher::new);
+    }
+
+    /// Returns the counter of finished hot reloads
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the counter of replacements and removals of the asset. Bindings of its GPU
+    /// buffers, like bind groups of models, must be recreated when it changes
+    pub fn revision_of<T: Any + Send + Sync>(&self, id: Id<T>) -> u64 {
+        self.maps
+            .get(&TypeId::of::<T>())
+            .and_then(|map| map.as_any().downcast_ref::<Map<T>>())
+            .map(|map| map.revision(id))
+            .unwrap_or(0)
+    }
+
+    /// Sends the resource to loaders, if there is an importer for its file extension
+    fn request_import(&mut self, id: Id<Resource>, path: PathBuf, data: Option<Vec<u8>>) {
+        let resource = self.get(id).expect("Resource must be stored");
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        let importer = extension.as_ref().and_then(|extension| self.importers.get(extension));
+
+        match importer {
+            Some(importer) => {
+                let task = Task {
+                    path,
+                    name: resource.name().clone(),
+                    resource: id,
+                    importer: Arc::clone(importer),
+                    data,
+                    vfs: Arc::clone(&self.vfs),
+                };
+                self.sender.send(Request::Import(task)).unwrap();
+            },
+            None => {
+                let error = ImportError::NotImplemented("extension", extension);
+                self.reloads.remove(&id);
+                self.set_state(id, State::Failed(error));
+            },
+        }
+    }
+
     /// stores new asset in the system under user defined name
-    pub fn store<T>(&mut self, asset: T, name: &str) -> Id<T>
-    where Self: AssetMapGetter<T> {
+    ///
+    /// An asset stored under the name before is replaced and its GPU buffers are unloaded
+    pub fn store<T: Any + Send + Sync>(&mut self, asset: T, name: &str) -> Id<T> {
         let id = self.register(name);
-        self.map_mut().insert(id, asset);
+        self.typed_map_mut().insert(id, asset);
         id
     }
 
     /// Registers user defined name for an asset
     pub fn register<T>(&mut self, name: &str) -> Id<T>
     where Self: AssetMapGetter<T> {
+        Id::new(self.register_name(name))
+    }
+
+    /// Registers user defined name for an asset of any type, returns raw id of the asset
+    pub(crate) fn register_name(&mut self, name: &str) -> RawId {
         let raw_id = self.next_id();
-        Id::new(*self.registry.entry(name.to_string()).or_insert(raw_id))
+        *self.registry.entry(name.to_string()).or_insert(raw_id)
+    }
+
+    /// Returns user defined name of an asset
+    pub fn name(&self, id: RawId) -> Option<&str> {
+        self.registry
+            .iter()
+            .find(|(_, raw_id)| **raw_id == id)
+            .map(|(name, _)| name.as_str())
     }
 
     pub fn find<T>(&self, name: &str) -> Option<Id<T>>
@@ -100,7 +244,7 @@ impl Assets {
     /// Gets an asset by the handle
     pub fn get<T>(&self, handle: Id<T>) -> Option<&T>
     where Self: AssetMapGetter<T> {
-        self.map().get(&handle)
+        self.map().and_then(|map| map.get(&handle))
     }
 
     /// Gets an asset by the handle
@@ -109,93 +253,163 @@ impl Assets {
         self.map_mut().get_mut(&handle)
     }
 
-    /// Returns an Id for an asset and increments the internal generator
-    fn next_id(&mut self) -> RawId {
-        let result = self.id_generator;
-        self.id_generator += 1;
-        result
+    /// Returns import state of the resource
+    pub fn state(&self, id: Id<Resource>) -> Option<&State> {
+        self.get(id).map(|resource| resource.state())
     }
 
-    pub fn fetch(&mut self) {
-        while let Ok(response) = self.receiver.try_recv() {
-            match response {
-                Response::Animation(animation) => {
-                    self.store(*animation.asset, &animation.name);
-                    //let id = self.find::<Animation>(animation.name.as_str());
-                    //self.map_mut().insert(id, animation.asset);
-                },
-                Response::Mesh(mesh) => {
-                    self.store(*mesh.asset, &mesh.name);
-                    //let id = self.find::<Mesh>(mesh.name.as_str());
-                    //self.map_mut().insert(id, mesh.asset);
-                },
-                Response::Skin(skin) => {
-                    self.store(*skin.asset, &skin.name);
-                    //let id = self.find::<Skin>(skin.name.as_str());
-                    //self.map_mut().insert(id, skin.asset);
-                },
-                Response::Texture(texture) => {
-                    self.store(*texture.asset, &texture.name);
-                    //let id = self.find::<Texture>(texture.name.as_str());
-                    //self.map_mut().insert(id, texture.asset);
-                },
-            };
+    /// Returns import progress of the group of resources. Unknown resources are never finished
+    pub fn progress(&self, resources: &[Id<Resource>]) -> Progress {
+        let mut progress = Progress { total: resources.len(), ..Default::default() };
+        for state in resources.iter().filter_map(|id| self.state(*id)) {
+            match state {
+                State::Loaded => progress.loaded += 1,
+                State::Failed(_) => progress.failed += 1,
+                _ => (),
+            }
         }
+        progress
     }
-}
 
-pub trait AssetMapGetter<T> {
-    fn map(&self) -> &HashMap<Id<T>, T>;
-    fn map_mut(&mut self) -> &mut HashMap<Id<T>, T>;
-}
+    /// Blocks until import of the resource is over or the timeout is reached, storing all
+    /// received assets. Returns the state of the resource
+    pub fn wait(&mut self, id: Id<Resource>, timeout: Duration) -> Option<&State> {
+        let deadline = Instant::now() + timeout;
+        self.fetch();
+        while self.state(id).map(|state| !state.is_finished()).unwrap_or(false) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(timeout) {
+                Ok(response) => self.receive(response),
+                Err(_) => break,
+            }
+        }
+        self.state(id)
+    }
 
-impl AssetMapGetter<Animation> for Assets {
-    fn map(&self) -> &HashMap<Id<Animation>, Animation> {
-        &self.animations
+    /// Removes the asset, returns it if it was stored
+    ///
+    /// Name of the asset stays registered, so an asset stored under the same name later gets
+    /// the same id. Weak handles of the removed asset could not be upgraded anymore
+    pub fn remove<T: Any + Send + Sync>(&mut self, id: Id<T>) -> Option<T> {
+        if let Some(counter) = self.handles.remove(&(TypeId::of::<T>(), id.id)) {
+            counter.remove();
+        }
+        self.typed_map_mut().remove(id)
     }
 
-    fn map_mut(&mut self) -> &mut HashMap<Id<Animation>, Animation> {
-        &mut self.animations
+    /// Returns a strong handle of the asset, so it will be freed by `collect_garbage` when all
+    /// strong handles are dropped
+    pub fn handle<T: 'static>(&mut self, id: Id<T>) -> Handle<T>
+    where Self: AssetMapGetter<T> {
+        let counter = self.handles
+            .entry((TypeId::of::<T>(), id.id))
+            .or_insert_with(|| Arc::new(Counter::default()));
+        Handle::new(id, Arc::clone(counter))
     }
-}
 
-impl AssetMapGetter<Texture> for Assets {
-    fn map(&self) -> &HashMap<Id<Texture>, Texture> {
-        &self.textures
+    /// Removes assets, that have no strong handles anymore, dropping their GPU buffers.
+    /// Returns number of removed assets
+    pub fn collect_garbage(&mut self) -> usize {
+        let garbage = self.handles
+            .iter()
+            .filter(|(_, counter)| Arc::strong_count(counter) == 1)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in garbage.iter() {
+            self.handles.remove(key);
+            if let Some(map) = self.maps.get_mut(&key.0) {
+                map.remove(key.1);
+            }
+        }
+        garbage.len()
     }
 
-    fn map_mut(&mut self) -> &mut HashMap<Id<Texture>, Texture> {
-        &mut self.textures
+    /// Returns memory usage statistics of assets by types
+    pub fn stats(&self) -> Vec<AssetStats> {
+        let mut stats = self.maps.values().map(|map| map.stats()).collect::<Vec<_>>();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats
     }
-}
 
-impl AssetMapGetter<Mesh> for Assets {
-    fn map(&self) -> &HashMap<Id<Mesh>, Mesh> {
-        &self.meshes
+    /// Returns an Id for an asset and increments the internal generator
+    fn next_id(&mut self) -> RawId {
+        let result = self.id_generator;
+        self.id_generator += 1;
+        result
     }
 
-    fn map_mut(&mut self) -> &mut HashMap<Id<Mesh>, Mesh> {
-        &mut self.meshes
+    /// Stores assets received from loaders and updates states of resources
+    pub fn fetch(&mut self) {
+        while let Ok(response) = self.receiver.try_recv() {
+            self.receive(response);
+        }
+
+        let modified = match (self.watcher.as_mut(), self.maps.get(&TypeId::of::<Resource>())) {
+            (Some(watcher), Some(resources)) => watcher.check(
+                &resources.as_any().downcast_ref::<Map<Resource>>().unwrap().assets,
+                &self.vfs,
+            ),
+            _ => return,
+        };
+        for id in modified {
+            let path = PathBuf::from(self.get(id).expect("Resource must be stored").path());
+            self.set_state(id, State::Queued);
+            self.reloads.insert(id);
+            self.request_import(id, path, None);
+        }
     }
-}
 
-impl AssetMapGetter<Skin> for Assets {
-    fn map(&self) -> &HashMap<Id<Skin>, Skin> {
-        &self.skins
+    fn receive(&mut self, response: Response) {
+        match response {
+            Response::Asset(asset) => asset.store(self),
+            Response::Loading(id) => self.set_state(id, State::Loading),
+            Response::Loaded(id) => {
+                if self.reloads.remove(&id) {
+                    self.revision += 1;
+                }
+                self.set_state(id, State::Loaded);
+            },
+            Response::Failed(id, error) => {
+                self.reloads.remove(&id);
+                self.set_state(id, State::Failed(error));
+            },
+        };
+    }
+
+    fn set_state(&mut self, id: Id<Resource>, state: State) {
+        if let Some(resource) = self.get_mut(id) {
+            resource.set_state(state);
+        }
     }
 
-    fn map_mut(&mut self) -> &mut HashMap<Id<Skin>, Skin> {
-        &mut self.skins
+    /// Returns the map of assets of the type, creating it if necessary
+    fn typed_map_mut<T: Any + Send + Sync>(&mut self) -> &mut Map<T> {
+        self.maps
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Map::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("Asset map must be of the asset type")
     }
 }
 
-impl AssetMapGetter<Resource> for Assets {
-    fn map(&self) -> &HashMap<Id<Resource>, Resource> {
-        &self.resources
+pub trait AssetMapGetter<T> {
+    fn map(&self) -> Option<&HashMap<Id<T>, T>>;
+    fn map_mut(&mut self) -> &mut HashMap<Id<T>, T>;
+}
+
+impl<T: Any + Send + Sync> AssetMapGetter<T> for Assets {
+    fn map(&self) -> Option<&HashMap<Id<T>, T>> {
+        self.maps.get(&TypeId::of::<T>()).map(|map| {
+            &map.as_any()
+                .downcast_ref::<Map<T>>()
+                .expect("Asset map must be of the asset type")
+                .assets
+        })
     }
 
-    fn map_mut(&mut self) -> &mut HashMap<Id<Resource>, Resource> {
-        &mut self.resources
+    fn map_mut(&mut self) -> &mut HashMap<Id<T>, T> {
+        &mut self.typed_map_mut::<T>().assets
     }
 }
 
@@ -218,3 +432,296 @@ impl Default for Assets {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    };
+
+    use super::{
+        AssetImporter,
+        Assets,
+        ImportContext,
+        ImportError,
+        Mesh,
+        Mount,
+        Pack,
+        PackBuilder,
+        State,
+        Texture,
+    };
+
+    /// Creates a directory, that is unique for the test run
+    fn temp_dir(name: &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+        let dir = std::env::temp_dir()
+            .join(format!("dotrix_{}_{}_{}", name, std::process::id(), nanos));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn import_states() {
+        let dir = temp_dir("import_states");
+        let path = dir.join("pixel.png");
+        image::RgbaImage::new(1, 1).save(&path).unwrap();
+
+        let mut assets = Assets::new();
+        let pixel = assets.import(path.to_str().unwrap());
+        let missing = assets.import(dir.join("missing.png").to_str().unwrap());
+        assert!(!assets.progress(&[pixel, missing]).is_finished());
+
+        let timeout = Duration::from_secs(10);
+        assert!(matches!(assets.wait(pixel, timeout), Some(State::Loaded)));
+        assert!(matches!(
+            assets.wait(missing, timeout),
+            Some(State::Failed(ImportError::FileRead(_)))
+        ));
+
+        let progress = assets.progress(&[pixel, missing]);
+        assert!(progress.is_finished() && !progress.is_ready());
+        assert_eq!(progress.loaded, 1);
+        let texture = assets.find::<Texture>("pixel").unwrap();
+        assert_eq!(assets.get(texture).map(|t| t.width), Some(1));
+    }
+
+    #[test]
+    fn hot_reload() {
+        let dir = temp_dir("hot_reload");
+        let path = dir.join("sprite.png");
+        image::RgbaImage::new(1, 1).save(&path).unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+
+        let mut assets = Assets::new();
+        let sprite = assets.import(path.to_str().unwrap());
+        let timeout = Duration::from_secs(10);
+        assert!(matches!(assets.wait(sprite, timeout), Some(State::Loaded)));
+        let texture = assets.find::<Texture>("sprite").unwrap();
+        assert_eq!(assets.revision_of(texture), 0);
+
+        assets.set_hot_reload(Some(Duration::from_secs(0)));
+        assets.fetch();
+        image::RgbaImage::new(2, 2).save(&path).unwrap();
+        // modification time may have a coarse resolution, so the file is rewritten until it moves
+        let deadline = Instant::now() + timeout;
+        while std::fs::metadata(&path).unwrap().modified().unwrap() == modified {
+            assert!(Instant::now() < deadline, "Modification time of the file is not changed");
+            std::thread::sleep(Duration::from_millis(10));
+            image::RgbaImage::new(2, 2).save(&path).unwrap();
+        }
+
+        let deadline = Instant::now() + timeout;
+        while assets.revision() == 0 && Instant::now() < deadline {
+            assets.fetch();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(assets.revision(), 1);
+        assert!(matches!(assets.state(sprite), Some(State::Loaded)));
+        assert_eq!(assets.find::<Texture>("sprite"), Some(texture));
+        assert_eq!(assets.get(texture).map(|t| t.width), Some(2));
+        assert_eq!(assets.revision_of(texture), 1);
+    }
+
+    #[test]
+    fn revisions() {
+        let mut assets = Assets::new();
+        let cube = assets.store(Mesh::cube(), "cube");
+        let other = assets.store(Mesh::cube(), "other");
+        assert_eq!(assets.revision_of(cube), 0);
+
+        assert_eq!(assets.store(Mesh::cube(), "cube"), cube);
+        assert_eq!(assets.revision_of(cube), 1);
+        assert!(assets.remove(cube).is_some());
+        assert_eq!(assets.revision_of(cube), 2);
+        assert_eq!(assets.revision_of(other), 0);
+        assert_eq!(assets.revision(), 0);
+    }
+
+    #[test]
+    fn garbage_collection() {
+        let mut assets = Assets::new();
+        let cube = assets.store(Mesh::cube(), "cube");
+        let kept = assets.store(Mesh::cube(), "kept");
+        let unmanaged = assets.store(Mesh::cube(), "unmanaged");
+
+        let handle = assets.handle(cube);
+        let weak = handle.downgrade();
+        let kept = assets.handle(kept);
+        assert_eq!(assets.collect_garbage(), 0);
+        assert!(weak.upgrade().is_some());
+
+        drop(handle);
+        assert_eq!(assets.collect_garbage(), 1);
+        assert!(assets.get(cube).is_none());
+        assert!(weak.upgrade().is_none());
+        assert!(assets.get(kept.id()).is_some());
+
+        let stats = assets.stats();
+        let meshes = stats.iter().find(|s| s.name == "Mesh").unwrap();
+        assert_eq!(meshes.count, 2);
+        assert!(meshes.bytes > 24 * std::mem::size_of::<[f32; 3]>());
+
+        assert!(assets.remove(unmanaged).is_some());
+        assert!(assets.remove(unmanaged).is_none());
+        assert_eq!(assets.store(Mesh::cube(), "unmanaged"), unmanaged);
+    }
+
+    #[test]
+    fn removed_handles() {
+        let mut assets = Assets::new();
+        let cube = assets.store(Mesh::cube(), "cube");
+        let handle = assets.handle(cube);
+        let weak = handle.downgrade();
+
+        assert!(assets.remove(cube).is_some());
+        assert!(weak.upgrade().is_none());
+
+        assets.store(Mesh::cube(), "cube");
+        let stored = assets.handle(cube);
+        assert!(weak.upgrade().is_none());
+        assert!(stored.downgrade().upgrade().is_some());
+    }
+
+    #[test]
+    fn stats_names() {
+        let mut assets = Assets::new();
+        assets.store(Some(Mesh::cube()), "cube");
+        assets.store(vec![Dialogue { lines: Vec::new() }], "dialogues");
+        assets.store([0u8; 4], "bytes");
+
+        let stats = assets.stats();
+        for name in ["Option<Mesh>", "Vec<Dialogue>", "[u8; 4]"].iter() {
+            assert!(stats.iter().any(|s| s.name == *name), "{} is not found", name);
+        }
+    }
+
+    struct Dialogue {
+        lines: Vec<String>,
+    }
+
+    struct DialogueImporter;
+
+    impl AssetImporter for DialogueImporter {
+        fn import(&self, ctx: &ImportContext, data: Vec<u8>) -> Result<(), ImportError> {
+            let text = String::from_utf8(data).map_err(|e| ImportError::Custom(Box::new(e)))?;
+            let lines = text.lines().map(String::from).collect();
+            ctx.send(ctx.name().to_string(), Dialogue { lines });
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn custom_importer() {
+        let dir = temp_dir("custom_importer");
+        std::fs::write(dir.join("intro.dlg"), "Hello\nBye").unwrap();
+        std::fs::write(dir.join("broken.dlg"), [0xff, 0xfe]).unwrap();
+
+        let mut assets = Assets::new();
+        assets.add_importer(&["dlg"], DialogueImporter);
+        let intro = assets.import(dir.join("intro.dlg").to_str().unwrap());
+        let broken = assets.import(dir.join("broken.dlg").to_str().unwrap());
+        let unknown = assets.import(dir.join("notes.txt").to_str().unwrap());
+
+        let timeout = Duration::from_secs(10);
+        assert!(matches!(assets.wait(intro, timeout), Some(State::Loaded)));
+        assert!(matches!(
+            assets.wait(broken, timeout),
+            Some(State::Failed(ImportError::Custom(_)))
+        ));
+        assert!(matches!(
+            assets.wait(unknown, timeout),
+            Some(State::Failed(ImportError::NotImplemented(_, Some(_))))
+        ));
+
+        let dialogue = assets.find::<Dialogue>("intro").unwrap();
+        assert_eq!(assets.get(dialogue).map(|d| d.lines.len()), Some(2));
+        assert!(assets.stats().iter().any(|s| s.name == "Dialogue" && s.count == 1));
+    }
+
+    #[test]
+    fn virtual_filesystem() {
+        let dir = temp_dir("virtual_filesystem");
+        let image = dir.join("pixel.png");
+        image::RgbaImage::new(1, 1).save(&image).unwrap();
+
+        let positions: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let mut builder = PackBuilder::new().compression(true);
+        builder.add("textures/pixel.png", std::fs::read(&image).unwrap()).unwrap();
+        builder.add("buffers/triangle.bin", bytemuck::cast_slice(&positions).to_vec()).unwrap();
+        builder.add("./docs/../notes.txt", vec![b'a'; 4096]).unwrap();
+        assert!(builder.add(image.to_str().unwrap(), Vec::new()).is_err());
+        assert!(builder.add("../notes.txt", Vec::new()).is_err());
+        assert_eq!(builder.len(), 3);
+        let pack = dir.join("game.pack");
+        builder.write(&mut std::fs::File::create(&pack).unwrap()).unwrap();
+        assert!(std::fs::metadata(&pack).unwrap().len() < 4096);
+        let notes = Pack::open(&pack).unwrap().read("notes.txt").unwrap().unwrap();
+        assert_eq!(notes, vec![b'a'; 4096]);
+
+        let gltf = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{ "primitives": [{ "attributes": { "POSITION": 0 } }] }],
+            "buffers": [{ "uri": "buffers/triangle.bin", "byteLength": 36 }],
+            "bufferViews": [{ "buffer": 0, "byteLength": 36 }],
+            "accessors": [{
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+            }]
+        }"#;
+
+        let mut assets = Assets::new();
+        assets.mount(Pack::open(&pack).unwrap());
+        let pixel = assets.import("textures/pixel.png");
+        let native = assets.import(image.to_str().unwrap());
+        let triangle = assets.import_bytes("triangle", "gltf", gltf.as_bytes().to_vec());
+
+        let timeout = Duration::from_secs(10);
+        assert!(matches!(assets.wait(pixel, timeout), Some(State::Loaded)));
+        assert!(matches!(assets.wait(triangle, timeout), Some(State::Loaded)));
+        assert!(matches!(assets.wait(native, timeout), Some(State::Loaded)));
+
+        let texture = assets.find::<Texture>("pixel").unwrap();
+        assert_eq!(assets.get(texture).map(|t| t.width), Some(1));
+        let mesh = assets.find::<Mesh>("triangle::mesh").unwrap();
+        assert_eq!(assets.get(mesh).map(|m| m.positions.clone()), Some(positions.to_vec()));
+    }
+
+    #[test]
+    fn corrupted_pack() {
+        let dir = temp_dir("corrupted_pack");
+        let mut builder = PackBuilder::new();
+        builder.add("notes.txt", vec![b'a'; 64]).unwrap();
+        let pack = dir.join("truncated.pack");
+        builder.write(&mut std::fs::File::create(&pack).unwrap()).unwrap();
+        let opened = Pack::open(&pack).unwrap();
+
+        let length = std::fs::metadata(&pack).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&pack).unwrap().set_len(length - 1).unwrap();
+        assert!(opened.read("notes.txt").unwrap().is_err());
+        assert!(Pack::open(&pack).is_err());
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"DXPK");
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.push(b'a');
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.push(0);
+        let pack = dir.join("oversized.pack");
+        std::fs::write(&pack, &data).unwrap();
+        assert!(Pack::open(&pack).is_err());
+
+        data[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&pack, &data).unwrap();
+        assert!(Pack::open(&pack).is_err());
+    }
+}
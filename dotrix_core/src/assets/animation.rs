@@ -4,6 +4,7 @@ use super::skin::JointId;
 
 use crate::renderer::transform::TransformBuilder;
 use dotrix_math::{ slerp, Vec3, Quat, VectorSpace };
+use super::usage::MemoryUsage;
 
 #[derive(Debug)]
 pub enum Interpolation {
@@ -196,3 +197,18 @@ impl Default for Animation {
         Self::new()
     }
 }
+
+impl MemoryUsage for Animation {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.translation_channels.iter().map(|c| c.memory_usage()).sum::<usize>()
+            + self.rotation_channels.iter().map(|c| c.memory_usage()).sum::<usize>()
+            + self.scale_channels.iter().map(|c| c.memory_usage()).sum::<usize>()
+    }
+}
+
+impl<T: Interpolate + Copy + Clone> MemoryUsage for Channel<T> {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + std::mem::size_of_val(self.keyframes.as_slice())
+    }
+}
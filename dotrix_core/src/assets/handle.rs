@@ -0,0 +1,113 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        Weak,
+    },
+};
+
+use super::id::Id;
+
+/// Reference counter of handles of an asset
+#[derive(Default)]
+pub(crate) struct Counter {
+    removed: AtomicBool,
+}
+
+impl Counter {
+    /// Marks the asset as removed, so weak handles could not be upgraded anymore
+    pub(crate) fn remove(&self) {
+        self.removed.store(true, Ordering::Relaxed);
+    }
+
+    fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Relaxed)
+    }
+}
+
+/// Strong reference counted handle of an asset
+///
+/// Assets with handles are freed by `Assets::collect_garbage`, when all their strong handles are
+/// dropped. Assets without handles are never collected.
+pub struct Handle<T> {
+    id: Id<T>,
+    counter: Arc<Counter>,
+}
+
+impl<T> Handle<T> {
+    pub(crate) fn new(id: Id<T>, counter: Arc<Counter>) -> Self {
+        Self {
+            id,
+            counter,
+        }
+    }
+
+    /// Returns id of the asset
+    pub fn id(&self) -> Id<T> {
+        self.id
+    }
+
+    /// Returns a weak handle of the asset, that does not prevent it from being collected
+    pub fn downgrade(&self) -> WeakHandle<T> {
+        WeakHandle {
+            id: self.id,
+            counter: Arc::downgrade(&self.counter),
+        }
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.id, Arc::clone(&self.counter))
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "Handle({:?})", self.id)
+    }
+}
+
+impl<T> From<&Handle<T>> for Id<T> {
+    fn from(handle: &Handle<T>) -> Self {
+        handle.id
+    }
+}
+
+/// Weak handle of an asset, that could be upgraded to the strong one until the asset is collected
+/// or removed
+pub struct WeakHandle<T> {
+    id: Id<T>,
+    counter: Weak<Counter>,
+}
+
+impl<T> WeakHandle<T> {
+    /// Returns id of the asset
+    pub fn id(&self) -> Id<T> {
+        self.id
+    }
+
+    /// Returns a strong handle, if the asset was not collected or removed yet
+    pub fn upgrade(&self) -> Option<Handle<T>> {
+        self.counter
+            .upgrade()
+            .filter(|counter| !counter.is_removed())
+            .map(|counter| Handle::new(self.id, counter))
+    }
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            counter: Weak::clone(&self.counter),
+        }
+    }
+}
+
+impl<T> Debug for WeakHandle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(f, "WeakHandle({:?})", self.id)
+    }
+}
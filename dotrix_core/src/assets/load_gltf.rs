@@ -1,8 +1,3 @@
-use std::{
-    path::PathBuf,
-    sync::{Arc, mpsc, Mutex},
-};
-
 use gltf::{
     Gltf,
     buffer::Source,
@@ -16,35 +11,43 @@ use dotrix_math::{Mat4, Vec3, Quat};
 
 use super::{
     animation::{Animation, Interpolation},
-    loader::{Asset, ImportError, Response, load_image},
+    loader::{AssetImporter, ImportContext, ImportError, load_image},
     mesh::Mesh,
     skin::{Skin, JointId, Joint, JointIndex},
 };
 
+/// Importer of meshes, textures, skins and animations from glTF files
+pub struct GltfImporter;
+
+impl AssetImporter for GltfImporter {
+    fn import(&self, ctx: &ImportContext, data: Vec<u8>) -> Result<(), ImportError> {
+        load_gltf(ctx, ctx.name(), data)
+    }
+}
+
 pub fn load_gltf(
-    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
-    name: String,
+    ctx: &ImportContext,
+    name: &str,
     data: Vec<u8>,
-    path: &PathBuf,
 ) -> Result<(), ImportError>{
 
     let gltf = Gltf::from_slice(&data)?;
-    let buffers = load_buffers(&gltf, path)?;
+    let buffers = load_buffers(ctx, &gltf)?;
 
     for scene in gltf.scenes() {
         for node in scene.nodes() {
-            load_node(sender, &name, &node, None, &buffers)?;
+            load_node(ctx, name, &node, None, &buffers)?;
         }
     }
 
     for animation in gltf.animations() {
-        load_animation(sender, &name, &animation, &buffers);
+        load_animation(ctx, name, &animation, &buffers);
     }
 
     Ok(())
 }
 
-fn load_buffers(gltf: &Gltf, path: &PathBuf) -> Result<Vec<Vec<u8>>, ImportError> {
+fn load_buffers(ctx: &ImportContext, gltf: &Gltf) -> Result<Vec<Vec<u8>>, ImportError> {
     const URI_BASE64: &str = "data:application/octet-stream;base64,";
     let mut buffers = Vec::new();
 
@@ -62,7 +65,7 @@ fn load_buffers(gltf: &Gltf, path: &PathBuf) -> Result<Vec<Vec<u8>>, ImportError
                     if let Some(stripped) = uri.strip_prefix(URI_BASE64) {
                         base64::decode(stripped)?
                     } else {
-                        std::fs::read(path.parent().unwrap().join(uri))?
+                        ctx.read(uri)?
                     }
                 );
             }
@@ -92,7 +95,7 @@ fn load_joints(
 }
 
 fn load_node(
-    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
+    ctx: &ImportContext,
     name: &str,
     node: &gltf::Node,
     root: Option<&gltf::Node>,
@@ -111,20 +114,15 @@ fn load_node(
         load_joints(&mut joints, skin.skeleton().as_ref().or(root).unwrap(), None);
 
         info!("importing skin as `{}`", asset_name);
-        sender.lock().unwrap().send(Response::Skin(
-            Asset {
-                name: asset_name,
-                asset: Box::new(Skin::new(joints, index, inverse_bind_matrices)),
-            }
-        )).unwrap();
+        ctx.send(asset_name, Skin::new(joints, index, inverse_bind_matrices));
     }
 
     if let Some(mesh) = node.mesh() {
         for primitive in mesh.primitives() {
-            load_mesh(sender, name, &primitive, buffers)?;
+            load_mesh(ctx, name, &primitive, buffers)?;
             let material = primitive.material();
             if let Some(texture) = material.pbr_metallic_roughness().base_color_texture() {
-                load_texture(sender, name, &texture, buffers)?;
+                load_texture(ctx, name, &texture, buffers)?;
             }
         }
     }
@@ -138,14 +136,14 @@ fn load_node(
         };
 
 
-        load_node(sender, &child_name, &child, root, buffers)?;
+        load_node(ctx, &child_name, &child, root, buffers)?;
     }
 
     Ok(())
 }
 
 fn load_mesh(
-    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
+    ctx: &ImportContext,
     name: &str,
     primitive: &gltf::Primitive,
     buffers: &[Vec<u8>],
@@ -181,18 +179,13 @@ fn load_mesh(
 
     mesh.calculate();
 
-    sender.lock().unwrap().send(Response::Mesh(
-        Asset {
-            name,
-            asset: Box::new(mesh),
-        }
-    )).unwrap();
+    ctx.send(name, mesh);
 
     Ok(())
 }
 
 fn load_texture(
-    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
+    ctx: &ImportContext,
     name: &str,
     texture: &gltf::texture::Info,
     buffers: &[Vec<u8>],
@@ -230,13 +223,13 @@ fn load_texture(
         }
     };
 
-    load_image(sender, name, data, format)?;
+    load_image(ctx, name, data, format)?;
 
     Ok(())
 }
 
 fn load_animation(
-    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
+    ctx: &ImportContext,
     name: &str,
     gltf_animation: &gltf::Animation,
     buffers: &[Vec<u8>],
@@ -275,12 +268,7 @@ fn load_animation(
         };
     }
 
-    sender.lock().unwrap().send(Response::Animation(
-        Asset {
-            name,
-            asset: Box::new(animation),
-        }
-    )).unwrap();
+    ctx.send(name, animation);
 }
 
 fn mode_to_string(mode: gltf::mesh::Mode) -> String {
@@ -1,6 +1,6 @@
 use std::{
-    fs::File,
-    path::PathBuf,
+    any::Any,
+    path::{Path, PathBuf},
     sync::{Arc, mpsc, Mutex},
     thread,
 };
@@ -8,16 +8,21 @@ use std::{
 use log::error;
 
 use super::{
-    animation::Animation,
-    mesh::Mesh,
-    skin::Skin,
+    Assets,
+    id::Id,
+    resource::Resource,
     texture::Texture,
-    load_gltf::load_gltf,
+    vfs::Vfs,
 };
 
 pub struct Task {
     pub path: PathBuf,
     pub name: String,
+    pub resource: Id<Resource>,
+    pub importer: Arc<dyn AssetImporter>,
+    /// Content of the file, if it was imported from memory
+    pub data: Option<Vec<u8>>,
+    pub vfs: Arc<Vfs>,
 }
 
 pub struct Asset<T> {
@@ -25,16 +30,70 @@ pub struct Asset<T> {
     pub asset: Box<T>,
 }
 
+/// Imported asset of any type, that could be stored in `Assets`
+pub trait Imported: Send {
+    fn store(self: Box<Self>, assets: &mut Assets);
+}
+
+impl<T: Any + Send + Sync> Imported for Asset<T> {
+    fn store(self: Box<Self>, assets: &mut Assets) {
+        assets.store(*self.asset, &self.name);
+    }
+}
+
 pub enum Request {
     Import(Task),
     Terminate,
 }
 
 pub enum Response {
-    Animation(Asset<Animation>),
-    Texture(Asset<Texture>),
-    Mesh(Asset<Mesh>),
-    Skin(Asset<Skin>),
+    /// Imported asset
+    Asset(Box<dyn Imported>),
+    /// Loader has started the import of the resource
+    Loading(Id<Resource>),
+    /// All assets of the resource were sent
+    Loaded(Id<Resource>),
+    /// Import of the resource has failed
+    Failed(Id<Resource>, ImportError),
+}
+
+/// Importer of assets from files of some format, registered in `Assets` by file extensions
+///
+/// Importers are called on loader threads.
+pub trait AssetImporter: Send + Sync {
+    /// Imports assets from the file data, sending them to `Assets` using the context
+    fn import(&self, ctx: &ImportContext, data: Vec<u8>) -> Result<(), ImportError>;
+}
+
+/// Context of a resource import
+pub struct ImportContext<'a> {
+    task: &'a Task,
+    sender: &'a Arc<Mutex<mpsc::Sender<Response>>>,
+}
+
+impl<'a> ImportContext<'a> {
+    /// Returns name of the imported resource
+    pub fn name(&self) -> &str {
+        &self.task.name
+    }
+
+    /// Returns path of the imported file
+    pub fn path(&self) -> &Path {
+        &self.task.path
+    }
+
+    /// Sends the asset to be stored under the name
+    pub fn send<T: Any + Send + Sync>(&self, name: String, asset: T) {
+        let asset = Asset { name, asset: Box::new(asset) };
+        self.sender.lock().unwrap().send(Response::Asset(Box::new(asset))).unwrap();
+    }
+
+    /// Reads a file referenced by the imported one through the virtual filesystem, relative
+    /// paths are resolved from the directory of the imported file
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, ImportError> {
+        let directory = self.task.path.parent().unwrap_or_else(|| Path::new(""));
+        Ok(self.task.vfs.read(&directory.join(path))?)
+    }
 }
 
 pub struct Loader {
@@ -49,6 +108,8 @@ pub enum ImportError {
     GltfDecode(gltf::Error),
     NotImplemented(&'static str, Option<String>),
     Corruption(&'static str),
+    /// Error of a user defined importer
+    Custom(Box<dyn std::error::Error + Send + Sync>),
 }
 
 impl Loader {
@@ -61,11 +122,17 @@ impl Loader {
             loop {
                 let request = receiver.lock().unwrap().recv().unwrap();
                 match request {
-                    Request::Import(task) => {
-                        if let Err(e) = import_resource(&task, &sender) {
-                            error!("[{}] Resource import from `{:?}` failed: \n\t{:?}",
-                                id, task.path, e);
-                        }
+                    Request::Import(mut task) => {
+                        sender.lock().unwrap().send(Response::Loading(task.resource)).unwrap();
+                        let response = match import_resource(&mut task, &sender) {
+                            Ok(()) => Response::Loaded(task.resource),
+                            Err(e) => {
+                                error!("[{}] Resource import from `{:?}` failed: \n\t{:?}",
+                                    id, task.path, e);
+                                Response::Failed(task.resource, e)
+                            }
+                        };
+                        sender.lock().unwrap().send(response).unwrap();
                     }, 
                     Request::Terminate => break,
                 }
@@ -85,37 +152,33 @@ impl Loader {
 }
 
 fn import_resource(
-    task: &Task,
+    task: &mut Task,
     sender: &Arc<Mutex<mpsc::Sender<Response>>>,
 ) -> Result<(), ImportError> {
 
-    use std::io::Read;
-
-    let name = String::from(task.path.file_stem().unwrap().to_str().unwrap());
-
-    let mut file = File::open(&task.path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
-
-    if let Some(extension) = task.path.extension() {
-        let extension = extension.to_str().unwrap();
-        match extension {
-            "png" | "jpg" | "jpeg" | "bmp" => load_image(
-                sender,
-                name,
-                buffer,
-                image::ImageFormat::from_extension(extension).unwrap(),
-            ),
-            "gltf" | "gltb" => load_gltf(sender, name, buffer, &task.path),
-            _ => Err(ImportError::NotImplemented("extension", None)),
-        }
-    } else {
-        Err(ImportError::NotImplemented("file without extension", None))
+    let buffer = match task.data.take() {
+        Some(data) => data,
+        None => task.vfs.read(&task.path)?,
+    };
+
+    task.importer.import(&ImportContext { task, sender }, buffer)
+}
+
+/// Importer of png, jpeg and bmp images as textures
+pub struct ImageImporter;
+
+impl AssetImporter for ImageImporter {
+    fn import(&self, ctx: &ImportContext, data: Vec<u8>) -> Result<(), ImportError> {
+        let format = ctx.path()
+            .extension()
+            .and_then(image::ImageFormat::from_extension)
+            .ok_or(ImportError::NotImplemented("image format", None))?;
+        load_image(ctx, ctx.name().to_string(), data, format)
     }
 }
 
 pub fn load_image(
-    sender: &Arc<Mutex<mpsc::Sender<Response>>>,
+    ctx: &ImportContext,
     name: String,
     data: Vec<u8>,
     format: image::ImageFormat,
@@ -126,17 +189,14 @@ pub fn load_image(
 
     let (width, height) = image.dimensions();
  
-    let texture = Asset {
-        name,
-        asset: Box::new(Texture {
-            width,
-            height,
-            depth: 1,
-            data: image.into_vec(),
-            ..Default::default()
-        })
+    let texture = Texture {
+        width,
+        height,
+        depth: 1,
+        data: image.into_vec(),
+        ..Default::default()
     };
-    sender.lock().unwrap().send(Response::Texture(texture)).unwrap();
+    ctx.send(name, texture);
     Ok(())
 }
 
@@ -157,6 +217,8 @@ impl std::fmt::Display for ImportError {
                 write!(f, "Not implemented support for the {:?} ({:?})", feature, variant),
             ImportError::Corruption(err) =>
                 write!(f, "File could be corrupted ({:?})", err),
+            ImportError::Custom(err) =>
+                write!(f, "Import failed ({})", err),
         }
     }
 }
@@ -0,0 +1,114 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+};
+
+use super::{
+    id::{Id, RawId},
+    usage::{AssetStats, MemoryUsage},
+};
+
+/// Type erased map of assets of the same type
+pub trait AssetMap: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// Drops the asset, returns false if it was not stored
+    fn remove(&mut self, id: RawId) -> bool;
+    fn stats(&self) -> AssetStats;
+}
+
+/// Assets of the same type
+pub struct Map<T> {
+    pub assets: HashMap<Id<T>, T>,
+    /// Returns memory usage of the asset
+    usage: fn(&T) -> usize,
+    /// Releases GPU buffers of the asset, when it is replaced or removed
+    unload: fn(&mut T),
+    /// Counters of replacements and removals of assets
+    revisions: HashMap<Id<T>, u64>,
+}
+
+impl<T: Any + Send + Sync> Map<T> {
+    /// Creates the map, memory usage of assets is counted as their inline size
+    pub fn new() -> Self {
+        Self {
+            assets: HashMap::new(),
+            usage: |_| std::mem::size_of::<T>(),
+            unload: |_| (),
+            revisions: HashMap::new(),
+        }
+    }
+
+    /// Counts memory usage of assets using the `MemoryUsage` trait
+    pub fn track_memory_usage(&mut self)
+    where
+        T: MemoryUsage,
+    {
+        self.usage = |asset| asset.memory_usage();
+    }
+
+    /// Sets the function releasing GPU buffers of replaced and removed assets
+    pub fn unload_with(&mut self, unload: fn(&mut T)) {
+        self.unload = unload;
+    }
+
+    /// Stores the asset. A replaced asset is unloaded and the revision of the id is incremented
+    pub fn insert(&mut self, id: Id<T>, asset: T) {
+        if let Some(mut replaced) = self.assets.insert(id, asset) {
+            (self.unload)(&mut replaced);
+            *self.revisions.entry(id).or_insert(0) += 1;
+        }
+    }
+
+    /// Drops the asset, unloading it and incrementing the revision of the id
+    pub fn remove(&mut self, id: Id<T>) -> Option<T> {
+        let mut asset = self.assets.remove(&id)?;
+        (self.unload)(&mut asset);
+        *self.revisions.entry(id).or_insert(0) += 1;
+        Some(asset)
+    }
+
+    /// Returns number of replacements and removals of the asset under the id
+    pub fn revision(&self, id: Id<T>) -> u64 {
+        self.revisions.get(&id).copied().unwrap_or(0)
+    }
+}
+
+impl<T: Any + Send + Sync> AssetMap for Map<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn remove(&mut self, id: RawId) -> bool {
+        Map::remove(self, Id::new(id)).is_some()
+    }
+
+    fn stats(&self) -> AssetStats {
+        AssetStats {
+            name: short_type_name(std::any::type_name::<T>()),
+            count: self.assets.len(),
+            bytes: self.assets.values().map(self.usage).sum(),
+        }
+    }
+}
+
+/// Strips module paths from the type name and from names of its generic arguments
+fn short_type_name(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut path = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+        } else {
+            result.push_str(path.rsplit("::").next().unwrap());
+            result.push(c);
+            path.clear();
+        }
+    }
+    result.push_str(path.rsplit("::").next().unwrap());
+    result
+}
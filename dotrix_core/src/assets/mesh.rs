@@ -1,6 +1,7 @@
 use bytemuck::{ Pod, Zeroable };
 use wgpu::util::DeviceExt;
 use dotrix_math::{ Vec3, InnerSpace, VectorSpace };
+use super::usage::MemoryUsage;
 
 #[derive(Default)]
 pub struct Mesh {
@@ -251,3 +252,15 @@ pub struct SkinnedModelVertex {
 unsafe impl Pod for SkinnedModelVertex {}
 unsafe impl Zeroable for SkinnedModelVertex {}
 impl VertexAttributes for SkinnedModelVertex {}
+
+impl MemoryUsage for Mesh {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + std::mem::size_of_val(self.positions.as_slice())
+            + self.normals.as_deref().map(std::mem::size_of_val).unwrap_or(0)
+            + self.uvs.as_deref().map(std::mem::size_of_val).unwrap_or(0)
+            + self.weights.as_deref().map(std::mem::size_of_val).unwrap_or(0)
+            + self.joints.as_deref().map(std::mem::size_of_val).unwrap_or(0)
+            + self.indices.as_deref().map(std::mem::size_of_val).unwrap_or(0)
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::vfs::{Mount, normalize};
+
+/// Signature of pack files
+const MAGIC: &[u8; 4] = b"DXPK";
+/// Version of the pack format
+const VERSION: u32 = 1;
+/// Flag of entries compressed with zlib
+const COMPRESSED: u8 = 0x01;
+/// Size of the header: magic, version and number of entries
+const HEADER_SIZE: u64 = 12;
+/// Size of an index entry without the path: path length, offset, sizes and flags
+const ENTRY_SIZE: u64 = 2 + 8 + 8 + 8 + 1;
+
+/// Location of a file inside of a pack
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    offset: u64,
+    /// Size of the stored, possibly compressed, data
+    stored_size: u64,
+    /// Size of the file
+    size: u64,
+    flags: u8,
+}
+
+impl Entry {
+    /// Returns true if the stored data fits into the pack of the length
+    fn fits(&self, length: u64) -> bool {
+        self.offset.checked_add(self.stored_size).map(|end| end <= length).unwrap_or(false)
+    }
+}
+
+/// Archive of files, that could be mounted to the virtual filesystem
+///
+/// Packs are built by `PackBuilder` or the `dotrix_pack` tool. All numbers are little endian:
+///
+/// ```text
+/// header:  b"DXPK", version: u32, number of entries: u32
+/// index:   path length: u16, path: [u8], offset: u64, stored size: u64, size: u64, flags: u8
+/// data:    content of files, compressed by zlib if the flags have the 0x01 bit
+/// ```
+///
+/// Only the index is kept in memory, files are read from the pack on demand.
+pub struct Pack {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+}
+
+impl Pack {
+    /// Opens the pack and reads its index. Packed files must be inside of the pack
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let length = file.metadata()?.len();
+        let mut file = io::BufReader::new(file);
+
+        let mut magic = [0; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a Dotrix pack"));
+        }
+        if read_u32(&mut file)? != VERSION {
+            return Err(invalid_data("Unsupported version of the pack"));
+        }
+
+        let count = read_u32(&mut file)?;
+        if u64::from(count) * ENTRY_SIZE > length - HEADER_SIZE {
+            return Err(invalid_data("Index of the pack does not fit into the file"));
+        }
+        let mut entries = HashMap::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut name = vec![0; read_u16(&mut file)? as usize];
+            file.read_exact(&mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|_| invalid_data("Path in the pack index is not UTF-8"))?;
+            let entry = Entry {
+                offset: read_u64(&mut file)?,
+                stored_size: read_u64(&mut file)?,
+                size: read_u64(&mut file)?,
+                flags: read_u8(&mut file)?,
+            };
+            if !entry.fits(length) {
+                return Err(invalid_data("Packed file does not fit into the pack"));
+            }
+            entries.insert(name, entry);
+        }
+
+        Ok(Self {
+            path,
+            entries,
+        })
+    }
+
+    /// Returns paths of packed files
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|name| name.as_str())
+    }
+
+    fn read_entry(&self, entry: &Entry) -> io::Result<Vec<u8>> {
+        let mut file = File::open(&self.path)?;
+        // the pack could be changed since it was opened
+        if !entry.fits(file.metadata()?.len()) {
+            return Err(invalid_data("Packed file does not fit into the pack"));
+        }
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut data = vec![0; entry.stored_size as usize];
+        file.read_exact(&mut data)?;
+
+        if entry.flags & COMPRESSED != 0 {
+            // the output buffer grows twice at a time, the exact size is checked below
+            let limit = usize::try_from(entry.size.saturating_mul(2))
+                .map_err(|_| invalid_data("Size of the packed file is too big"))?;
+            data = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&data, limit)
+                .map_err(|_| invalid_data("Can't decompress the packed file"))?;
+        }
+        if data.len() as u64 != entry.size {
+            return Err(invalid_data("Size of the packed file does not match the index"));
+        }
+        Ok(data)
+    }
+}
+
+impl Mount for Pack {
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>> {
+        self.entries.get(path).map(|entry| self.read_entry(entry))
+    }
+}
+
+/// Builder of packs
+#[derive(Default)]
+pub struct PackBuilder {
+    files: BTreeMap<String, Vec<u8>>,
+    compression: bool,
+}
+
+impl PackBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables zlib compression of files, files that do not shrink are stored as they are
+    pub fn compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Adds the file under the path, replacing a file added under the same path before.
+    /// Absolute paths and paths leading out of the root are rejected
+    pub fn add(&mut self, path: &str, data: Vec<u8>) -> io::Result<()> {
+        let path = normalize(Path::new(path)).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Path must be relative to the pack")
+        })?;
+        self.files.insert(path, data);
+        Ok(())
+    }
+
+    /// Adds all files of the directory and its subdirectories under paths relative to it
+    pub fn add_directory<P: AsRef<Path>>(&mut self, directory: P) -> io::Result<()> {
+        let directory = directory.as_ref();
+        let mut directories = vec![directory.to_path_buf()];
+        while let Some(current) = directories.pop() {
+            for entry in std::fs::read_dir(&current)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else {
+                    let name = path.strip_prefix(directory).expect("Path must be in directory");
+                    self.add(&name.to_string_lossy(), std::fs::read(&path)?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns number of added files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns true if no files were added
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Writes the pack
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut files = Vec::with_capacity(self.files.len());
+        for (name, data) in self.files.iter() {
+            if u16::try_from(name.len()).is_err() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Path is too long"));
+            }
+            let compressed = if self.compression {
+                Some(miniz_oxide::deflate::compress_to_vec_zlib(data, 6))
+                    .filter(|compressed| compressed.len() < data.len())
+            } else {
+                None
+            };
+            files.push((name, data, compressed));
+        }
+
+        let index_size = files
+            .iter()
+            .map(|(name, _, _)| ENTRY_SIZE + name.len() as u64)
+            .sum::<u64>();
+        let count = u32::try_from(files.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many files"))?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&count.to_le_bytes())?;
+
+        let mut offset = HEADER_SIZE + index_size;
+        for (name, data, compressed) in files.iter() {
+            let stored_size = compressed.as_ref().unwrap_or(data).len() as u64;
+            let flags = if compressed.is_some() { COMPRESSED } else { 0 };
+            writer.write_all(&(name.len() as u16).to_le_bytes())?;
+            writer.write_all(name.as_bytes())?;
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&stored_size.to_le_bytes())?;
+            writer.write_all(&(data.len() as u64).to_le_bytes())?;
+            writer.write_all(&[flags])?;
+            offset += stored_size;
+        }
+
+        for (_, data, compressed) in files.iter() {
+            writer.write_all(compressed.as_ref().unwrap_or(data))?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
@@ -1,18 +1,60 @@
-/*
- * TODO: Implement resources state control
-use std::vec::Vec;
+use super::{loader::ImportError, usage::MemoryUsage};
+
+/// Import state of a resource
+#[derive(Debug)]
 pub enum State {
-    Busy,
-    Ready,
-    Fail,
+    /// Import was requested, but no loader has started it yet
+    Queued,
+    /// Resource is being imported by a loader
+    Loading,
+    /// All assets of the resource were imported and stored
+    Loaded,
+    /// Import has failed, assets stored before the failure are kept
+    Failed(ImportError),
+}
+
+impl State {
+    /// Returns true if the import is over, successfully or not
+    pub fn is_finished(&self) -> bool {
+        matches!(self, State::Loaded | State::Failed(_))
+    }
+}
+
+/// Import progress of a group of resources, useful for loading screens
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of resources in the group
+    pub total: usize,
+    /// Number of loaded resources
+    pub loaded: usize,
+    /// Number of resources failed to import
+    pub failed: usize,
+}
+
+impl Progress {
+    /// Returns true if imports of all resources are over, successfully or not
+    pub fn is_finished(&self) -> bool {
+        self.loaded + self.failed == self.total
+    }
+
+    /// Returns true if all resources were loaded
+    pub fn is_ready(&self) -> bool {
+        self.loaded == self.total
+    }
+
+    /// Returns part of finished imports in range from 0.0 to 1.0
+    pub fn ratio(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (self.loaded + self.failed) as f32 / self.total as f32
+    }
 }
-*/
 
 pub struct Resource {
     name: String,
     path: String,
-//    state: State,
-//    data: Option<Vec<u8>>,
+    state: State,
 }
 
 impl Resource {
@@ -20,8 +62,7 @@ impl Resource {
         Self {
             name,
             path,
-//            state: State::Busy,
-//            data: None,
+            state: State::Queued,
         }
     }
 
@@ -32,5 +73,18 @@ impl Resource {
     pub fn name(&self) -> &String {
         &self.name
     }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub(crate) fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
 }
 
+impl MemoryUsage for Resource {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.name.len() + self.path.len()
+    }
+}
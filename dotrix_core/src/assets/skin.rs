@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use super::super::renderer::transform::{ Transform, TransformBuilder };
 use dotrix_math::{Mat4, SquareMatrix};
+use super::usage::MemoryUsage;
 
 pub type JointId = usize;
 
@@ -81,18 +82,21 @@ impl Skin {
         self.joints.iter().position(|j| j.id == joint_id).unwrap()
     }
 
+    /// Poses joints in the model space
+    ///
+    /// Transformation of the model itself is not applied to the joints: the skinned shader
+    /// multiplies skinned vertices by the model matrix, which the renderer fills with the
+    /// `GlobalTransform` of the entity, so skinned models follow their parents like static ones
     pub fn transform(
         &self,
         skin_transform: &mut Pose,
-        model_transform: &Mat4,
         local_transforms: Option<HashMap<JointId, TransformBuilder>>,
     ) {
 
         for (i, joint) in self.joints.iter().enumerate() {
             let parent_transform = joint.parent_id
                 .map(|parent_id| skin_transform.joints[self.index(parent_id)].global_transform)
-                .or(Some(*model_transform))
-                .unwrap();
+                .unwrap_or_else(Mat4::identity);
 
             let local_transform = local_transforms
                 .as_ref()
@@ -168,3 +172,11 @@ impl Pose {
     }
 }
 
+impl MemoryUsage for Skin {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + std::mem::size_of_val(self.joints.as_slice())
+            + self.joints.iter().filter_map(|j| j.name.as_ref()).map(|n| n.len()).sum::<usize>()
+            + std::mem::size_of_val(self.index.as_slice())
+    }
+}
@@ -1,3 +1,5 @@
+use super::usage::MemoryUsage;
+
 
 #[derive(Default)]
 pub struct Texture {
@@ -57,3 +59,9 @@ impl Texture {
         self.view.as_ref().unwrap()
     }
 }
+
+impl MemoryUsage for Texture {
+    fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.data.len()
+    }
+}
@@ -0,0 +1,15 @@
+/// Approximate size of an asset in CPU memory, GPU buffers are not counted
+pub trait MemoryUsage {
+    fn memory_usage(&self) -> usize;
+}
+
+/// Memory usage statistics of assets of the same type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetStats {
+    /// Name of the asset type without module paths
+    pub name: String,
+    /// Number of stored assets
+    pub count: usize,
+    /// Approximate size of the assets in bytes
+    pub bytes: usize,
+}
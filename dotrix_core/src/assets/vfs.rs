@@ -0,0 +1,114 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    sync::RwLock,
+    time::SystemTime,
+};
+
+/// Source of files mounted to the virtual filesystem
+///
+/// Paths passed to mounts are normalized: relative, separated by `/`, without `.` and `..`.
+/// Absolute paths and paths leading out of the root are never passed to mounts
+pub trait Mount: Send + Sync {
+    /// Reads the file, returns `None` if the mount has no file under the path
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>>;
+
+    /// Returns modification time of the file, if the mount has the file and tracks the time
+    fn modified(&self, _path: &str) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// Directory of the native filesystem mounted to the virtual filesystem
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+        }
+    }
+}
+
+impl Mount for Directory {
+    fn read(&self, path: &str) -> Option<io::Result<Vec<u8>>> {
+        let path = self.root.join(path);
+        if path.is_file() {
+            Some(std::fs::read(path))
+        } else {
+            None
+        }
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        std::fs::metadata(self.root.join(path))
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .and_then(|metadata| metadata.modified().ok())
+    }
+}
+
+/// Virtual filesystem, that resolves paths of imported files through mounted directories and
+/// packs
+///
+/// Mounts are searched from the last mounted one, so later mounts shadow files of the earlier
+/// ones. Paths not found in any mount and absolute paths are read from the native filesystem as
+/// they are.
+#[derive(Default)]
+pub struct Vfs {
+    mounts: RwLock<Vec<Box<dyn Mount>>>,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mounts the source of files
+    pub fn mount<M: Mount + 'static>(&self, mount: M) {
+        self.mounts.write().unwrap().push(Box::new(mount));
+    }
+
+    /// Reads the file
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        if let Some(normalized) = normalize(path) {
+            for mount in self.mounts.read().unwrap().iter().rev() {
+                if let Some(result) = mount.read(&normalized) {
+                    return result;
+                }
+            }
+        }
+        std::fs::read(path)
+    }
+
+    /// Returns modification time of the file, if it is known
+    pub fn modified(&self, path: &Path) -> Option<SystemTime> {
+        if let Some(normalized) = normalize(path) {
+            for mount in self.mounts.read().unwrap().iter().rev() {
+                if let Some(modified) = mount.modified(&normalized) {
+                    return Some(modified);
+                }
+            }
+        }
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
+
+/// Converts the path to the form used by mounts, returns `None` for absolute paths and paths
+/// leading out of the root
+pub fn normalize(path: &Path) -> Option<String> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => components.push(name.to_string_lossy()),
+            Component::ParentDir => {
+                components.pop()?;
+            },
+            Component::CurDir => (),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(components.join("/"))
+}
@@ -0,0 +1,59 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{
+    id::Id,
+    resource::Resource,
+    vfs::Vfs,
+};
+
+/// Polls modification time of imported files
+pub struct Watcher {
+    interval: Duration,
+    last_check: Option<Instant>,
+    modified: HashMap<Id<Resource>, SystemTime>,
+}
+
+impl Watcher {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_check: None,
+            modified: HashMap::new(),
+        }
+    }
+
+    /// Returns resources, which files were modified since the previous check. Files seen for
+    /// the first time and resources, that are still being imported, are not reported
+    pub fn check(
+        &mut self,
+        resources: &HashMap<Id<Resource>, Resource>,
+        vfs: &Vfs,
+    ) -> Vec<Id<Resource>> {
+        let now = Instant::now();
+        if self.last_check.map(|last| now - last < self.interval).unwrap_or(false) {
+            return Vec::new();
+        }
+        self.last_check = Some(now);
+
+        let mut result = Vec::new();
+        for (id, resource) in resources.iter().filter(|(_, r)| r.state().is_finished()) {
+            if resource.path().is_empty() {
+                continue;
+            }
+            let modified = match vfs.modified(Path::new(resource.path())) {
+                Some(modified) => modified,
+                None => continue,
+            };
+            if let Some(previous) = self.modified.insert(*id, modified) {
+                if previous != modified {
+                    result.push(*id);
+                }
+            }
+        }
+        result
+    }
+}
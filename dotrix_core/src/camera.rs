@@ -3,7 +3,7 @@ use crate::{
     services::{ Input, Frame },
 };
 
-use dotrix_math::{Mat4, Point3, Vec3};
+use dotrix_math::{Mat4, Point3, Vec2, Vec3};
 use std::f32::consts::PI;
 
 const ROTATE_SPEED: f32 = PI / 10.0;
@@ -77,10 +77,11 @@ impl Default for Camera {
     }
 }
 
-pub fn camera_control(mut camera: Mut<Camera>, input: Const<Input>, frame: Const<Frame>) {
+pub fn camera_control(mut camera: Mut<Camera>, input: Option<Const<Input>>, frame: Const<Frame>) {
     let time_delta = frame.delta().as_secs_f32();
-    let mouse_delta = input.mouse_delta();
-    let mouse_scroll = input.mouse_scroll();
+    let (mouse_delta, mouse_scroll) = input
+        .map(|input| (input.mouse_delta(), input.mouse_scroll()))
+        .unwrap_or_else(|| (Vec2::new(0.0, 0.0), 0.0));
 
     let distance = camera.distance - ZOOM_SPEED * mouse_scroll * time_delta;
     camera.distance = if distance > -1.0 { distance } else { -1.0 };
@@ -1,12 +1,48 @@
 use core::ops::{Deref, DerefMut};
+use std::any::TypeId;
+
+use crate::{
+    application::{
+        services::Services,
+        Service
+    },
+    world::{ Archetype, Pattern, World },
+};
 
-use crate::application::{
-    services::Services,
-    Service
+pub use crate::{
+    state::{ InState, OnEnter, OnExit },
+    world::{ Or, Ref, StorageType, With, Without },
 };
+use crate::state::{ Condition, Conditional, Transition };
+
+/// Entity handle represents an agregation of components
+///
+/// Handle consists of an index of the entity slot in the World and a generation of the slot, so
+/// handles of despawned entities could be detected even if the slot was reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        Self {
+            index,
+            generation,
+        }
+    }
+
+    /// Returns index of the entity slot
+    pub fn index(&self) -> u32 {
+        self.index
+    }
 
-/// Entity structure has only id field and represent an agregation of components
-pub struct Entity(u64);
+    /// Returns generation of the entity slot
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
 
 /// Any data structure can be a component
 pub trait Component: Send + Sync + 'static {}
@@ -15,12 +51,20 @@ impl<T: Send + Sync + 'static> Component for T {}
 pub struct System {
     data: Box<dyn Systemized>,
     run_level: RunLevel,
+    order: Order,
+    conditions: Vec<Condition>,
 }
 
 pub enum RunLevel {
     Standard,
     Startup,
     Render,
+    /// Systems running with fixed frequency in Hz, zero or more times per frame
+    Fixed(u32),
+    /// Systems running once before the application terminates
+    Shutdown,
+    /// Systems running once on a state transition, set by `OnEnter` and `OnExit` options
+    Transition(Transition),
 }
 
 impl System {
@@ -33,6 +77,8 @@ impl System {
         Self {
             data: func.into_system(),
             run_level: RunLevel::Standard,
+            order: Order::default(),
+            conditions: Vec::new(),
         }
     }
 
@@ -44,11 +90,40 @@ impl System {
         self
     }
 
-    pub fn tuple(self) -> (Box<dyn Systemized>, RunLevel) {
-        (self.data, self.run_level)
+    pub fn tuple(self) -> (Box<dyn Systemized>, RunLevel, Order) {
+        let data = if self.conditions.is_empty() {
+            self.data
+        } else {
+            Box::new(Conditional::new(self.data, self.conditions))
+        };
+        (data, self.run_level, self.order)
+    }
+
+    /// Adds a condition, that must be met to run the system
+    pub(crate) fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push(condition);
     }
 }
 
+/// Label of a system, that can be used in ordering constraints of other systems. Several systems
+/// can share the same label.
+pub struct Label(pub &'static str);
+
+/// Constraint to run a system before all systems with the label
+pub struct Before(pub &'static str);
+
+/// Constraint to run a system after all systems with the label
+pub struct After(pub &'static str);
+
+/// Labels and ordering constraints of a system. Constraints are applied only to systems of the
+/// same `RunLevel`.
+#[derive(Default)]
+pub struct Order {
+    pub labels: Vec<&'static str>,
+    pub before: Vec<&'static str>,
+    pub after: Vec<&'static str>,
+}
+
 pub trait SystemOption<T> {
     fn set_option(&mut self, option: T);
 }
@@ -59,31 +134,103 @@ impl SystemOption<RunLevel> for System {
     }
 }
 
-struct SystemData<Run, Ctx> 
+impl SystemOption<Label> for System {
+    fn set_option(&mut self, option: Label) {
+        self.order.labels.push(option.0);
+    }
+}
+
+impl SystemOption<Before> for System {
+    fn set_option(&mut self, option: Before) {
+        self.order.before.push(option.0);
+    }
+}
+
+impl SystemOption<After> for System {
+    fn set_option(&mut self, option: After) {
+        self.order.after.push(option.0);
+    }
+}
+
+struct SystemData<Run, Ctx, State> 
 where
-    Run: FnMut(&mut Ctx, &mut Services) + Send + Sync,
+    Run: FnMut(&mut Ctx, &mut State, &Services) + Send + Sync,
 {
     name: &'static str,
     run: Run,
     ctx: Ctx,
+    /// States of the system accessors
+    state: State,
+    apply: fn(&mut State, &mut Services),
+    access: Vec<Access>,
 }
 
 pub trait Systemized: Send + Sync {
-    fn name(&mut self) -> &'static str;
-    fn run(&mut self, app: &mut Services);
+    fn name(&self) -> &'static str;
+    fn run(&mut self, app: &Services);
+    fn access(&self) -> &[Access];
+    /// Applies deferred changes made by the system, called by the scheduler at the sync point
+    /// after the system run
+    fn apply(&mut self, app: &mut Services);
 }
 
-impl<Run, Ctx> Systemized for SystemData<Run, Ctx>
+impl<Run, Ctx, State> Systemized for SystemData<Run, Ctx, State>
 where
-    Run: FnMut(&mut Ctx, &mut Services) + Send + Sync,
+    Run: FnMut(&mut Ctx, &mut State, &Services) + Send + Sync,
     Ctx: SystemContext,
+    State: Send + Sync,
 {
-    fn name(&mut self) -> &'static str {
+    fn name(&self) -> &'static str {
         self.name
     }
 
-    fn run(&mut self, app: &mut Services) {
-        (self.run)(&mut self.ctx, app);
+    fn run(&mut self, app: &Services) {
+        (self.run)(&mut self.ctx, &mut self.state, app);
+    }
+
+    fn access(&self) -> &[Access] {
+        &self.access
+    }
+
+    fn apply(&mut self, app: &mut Services) {
+        (self.apply)(&mut self.state, app);
+    }
+}
+
+/// Access of a system to a service
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub type_id: TypeId,
+    pub name: &'static str,
+    pub mutable: bool,
+}
+
+impl Access {
+    pub fn of<T: Service>(mutable: bool) -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+            mutable,
+        }
+    }
+
+    /// Checks if the access conflicts with the other one
+    pub fn conflicts(&self, other: &Access) -> bool {
+        self.type_id == other.type_id && (self.mutable || other.mutable)
+    }
+}
+
+/// Panics if a system requests a service mutably more than once
+fn validate(system: &'static str, access: &[Access]) {
+    for (i, first) in access.iter().enumerate() {
+        if let Some(second) = access[i + 1..].iter().find(|second| first.conflicts(second)) {
+            let how = if first.mutable && second.mutable {
+                "mutably more than once"
+            } else {
+                "mutably and immutably at the same time"
+            };
+            panic!("System `{}` accesses service `{}` {}", system, first.name, how);
+        }
     }
 }
 
@@ -104,20 +251,36 @@ macro_rules! impl_into_system {
             #[allow(unused)]
             fn into_system(mut self: Fun) -> Box<dyn Systemized>
             {
-                let data: SystemData<_, ($($context)*)> = SystemData {
-                    name: std::any::type_name::<Fun>(),
-                    run: move |ctx, app| {
+                let name = std::any::type_name::<Fun>();
+                let access = vec![$($i::access(),)*]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                validate(name, &access);
+
+                let data: SystemData<_, ($($context)*), ($($i::State,)*)> = SystemData {
+                    name,
+                    run: move |ctx, state, app| {
                         // context (none or one)
                         $(
                             let $context = Context::new(ctx);
                         )*
                         // services
+                        let ($($i,)*) = state;
                         $(
-                            let $i = $i::fetch(app);
+                            let $i = $i::fetch(app, $i);
                         )*
                         (self)($($context,)* $($i,)*);
                     },
                     ctx: ($($context::default())*),
+                    state: Default::default(),
+                    apply: |state, app| {
+                        let ($($i,)*) = state;
+                        $(
+                            $i::apply($i, app);
+                        )*
+                    },
+                    access,
                 };
                 Box::new(data)
             }
@@ -235,7 +398,13 @@ unsafe impl<T: Service> Sync for Const<T> {}
 
 pub trait Accessor: Send + Sync {
     type Item: Service;
-    fn fetch(app: &mut Services) -> Self;
+    /// Data kept by the system for the accessor between runs
+    type State: Default + Send + Sync + 'static;
+    fn fetch(app: &Services, state: &mut Self::State) -> Self;
+    /// Returns access to a service, if the accessor requires any
+    fn access() -> Option<Access>;
+    /// Applies the state at the sync point after the system run
+    fn apply(_state: &mut Self::State, _app: &mut Services) {}
 }
 
 impl<T> Accessor for Mut<T>
@@ -243,13 +412,18 @@ where
     T: Service,
 {
     type Item = T;
-    fn fetch(services: &mut Services) -> Self {
-        let service: &mut T = services.get_mut::<T>()
+    type State = ();
+    fn fetch(services: &Services, _: &mut ()) -> Self {
+        let service: *mut T = services.get_ptr::<T>()
             .unwrap_or_else(|| panic!("Service {} does not exist", std::any::type_name::<T>()));
         Mut {
-            value: service as *mut T
+            value: service
         }
     }
+
+    fn access() -> Option<Access> {
+        Some(Access::of::<T>(true))
+    }
 }
 
 impl<T> Accessor for Const<T>
@@ -257,13 +431,220 @@ where
     T: Service,
 {
     type Item = T;
-    fn fetch(service: &mut Services) -> Self {
+    type State = ();
+    fn fetch(service: &Services, _: &mut ()) -> Self {
         let service: &T = service.get::<T>()
             .unwrap_or_else(|| panic!("Service {} does not exist", std::any::type_name::<T>()));
         Const {
             value: service as *const T
         }
     }
+
+    fn access() -> Option<Access> {
+        Some(Access::of::<T>(false))
+    }
+}
+
+/// Optional mutable access to a service, that is `None` if the service does not exist
+impl<T> Accessor for Option<Mut<T>>
+where
+    T: Service,
+{
+    type Item = T;
+    type State = ();
+    fn fetch(services: &Services, _: &mut ()) -> Self {
+        services.get_ptr::<T>().map(|service| Mut { value: service })
+    }
+
+    fn access() -> Option<Access> {
+        Some(Access::of::<T>(true))
+    }
+}
+
+/// Optional immutable access to a service, that is `None` if the service does not exist
+impl<T> Accessor for Option<Const<T>>
+where
+    T: Service,
+{
+    type Item = T;
+    type State = ();
+    fn fetch(services: &Services, _: &mut ()) -> Self {
+        services.get::<T>().map(|service| Const { value: service as *const T })
+    }
+
+    fn access() -> Option<Access> {
+        Some(Access::of::<T>(false))
+    }
+}
+
+/// Deferred change of the World
+type Command = Box<dyn FnOnce(&mut World) + Send + Sync>;
+
+/// Buffer of deferred changes of the World recorded by a system
+#[derive(Default)]
+pub struct CommandBuffer {
+    commands: Vec<Command>,
+}
+
+impl CommandBuffer {
+    /// Returns the accessor recording commands to the buffer
+    pub(crate) fn commands(&mut self) -> Commands {
+        Commands {
+            buffer: self as *mut CommandBuffer,
+        }
+    }
+
+    /// Applies recorded commands to the World in the order they were recorded
+    pub(crate) fn apply(&mut self, world: &mut World) {
+        for command in self.commands.drain(..) {
+            command(world);
+        }
+    }
+}
+
+/// Accessor recording structural changes of the World, like spawning and despawning of
+/// entities, while the system iterates queries
+///
+/// Changes are applied by the scheduler in the order they were recorded, after all systems of
+/// the batch, that the system belongs to, are finished. So systems running after it will see the
+/// changes, while systems running in parallel with it will not.
+pub struct Commands {
+    buffer: *mut CommandBuffer,
+}
+
+impl Commands {
+    /// Spawns an entity with the set of components
+    pub fn spawn<T>(&mut self, components: T)
+    where
+        T: Archetype + Pattern + Component,
+    {
+        self.add(move |world| {
+            world.spawn(Some(components));
+        });
+    }
+
+    /// Despawns the entity with all its components
+    pub fn despawn(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    /// Inserts a component to the entity
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) {
+        self.add(move |world| {
+            world.insert(entity, component);
+        });
+    }
+
+    /// Removes a component from the entity
+    pub fn remove<T: Component>(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+
+    /// Records a custom change of the World
+    pub fn add<F>(&mut self, command: F)
+    where
+        F: FnOnce(&mut World) + Send + Sync + 'static,
+    {
+        let buffer = unsafe { &mut *self.buffer };
+        buffer.commands.push(Box::new(command));
+    }
+}
+
+unsafe impl Send for Commands {}
+unsafe impl Sync for Commands {}
+
+impl Accessor for Commands {
+    type Item = World;
+    type State = CommandBuffer;
+    fn fetch(_: &Services, state: &mut CommandBuffer) -> Self {
+        state.commands()
+    }
+
+    fn access() -> Option<Access> {
+        None
+    }
+
+    fn apply(state: &mut CommandBuffer, app: &mut Services) {
+        if state.commands.is_empty() {
+            return;
+        }
+        if let Some(world) = app.get_mut::<World>() {
+            state.apply(world);
+        } else {
+            log::warn!("World service does not exist, {} commands are dropped", state.commands.len());
+            state.commands.clear();
+        }
+    }
+}
+
+/// Deferred change of services
+type ServiceCommand = Box<dyn FnOnce(&mut Services) + Send + Sync>;
+
+/// Buffer of deferred changes of services recorded by a system
+#[derive(Default)]
+pub struct ServiceBuffer {
+    commands: Vec<ServiceCommand>,
+}
+
+/// Accessor replacing and removing services at runtime, for example to swap input mappers
+/// between game modes
+///
+/// Changes are applied by the scheduler in the order they were recorded, after all systems of
+/// the batch, that the system belongs to, are finished, so no system holds the replaced service
+/// at that moment.
+pub struct ServiceCommands {
+    buffer: *mut ServiceBuffer,
+}
+
+impl ServiceCommands {
+    /// Replaces the service with the new instance or adds it, if the service does not exist
+    pub fn replace<T: Service>(&mut self, service: T) {
+        self.add(move |services| {
+            services.replace(service);
+        });
+    }
+
+    /// Removes the service
+    pub fn remove<T: Service>(&mut self) {
+        self.add(|services| {
+            services.remove::<T>();
+        });
+    }
+
+    fn add<F>(&mut self, command: F)
+    where
+        F: FnOnce(&mut Services) + Send + Sync + 'static,
+    {
+        let buffer = unsafe { &mut *self.buffer };
+        buffer.commands.push(Box::new(command));
+    }
+}
+
+unsafe impl Send for ServiceCommands {}
+unsafe impl Sync for ServiceCommands {}
+
+impl Accessor for ServiceCommands {
+    type Item = ();
+    type State = ServiceBuffer;
+    fn fetch(_: &Services, state: &mut ServiceBuffer) -> Self {
+        ServiceCommands {
+            buffer: state as *mut ServiceBuffer,
+        }
+    }
+
+    fn access() -> Option<Access> {
+        None
+    }
+
+    fn apply(state: &mut ServiceBuffer, app: &mut Services) {
+        for command in state.commands.drain(..) {
+            command(app);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -271,7 +652,10 @@ mod tests {
     use crate::{
         application::services::Services,
         ecs::{
+            Commands,
             Context,
+            Const,
+            Entity,
             System,
             Mut,
         },
@@ -289,7 +673,7 @@ mod tests {
         let mut services = Services::new();
         services.add(World::new());
         let mut s = System::from(my_system);
-        s.data.run(&mut services);
+        s.data.run(&services);
         assert_eq!(services.get::<World>().unwrap().counter(), 1);
     }
 
@@ -309,8 +693,71 @@ mod tests {
         let mut services = Services::new();
         services.add(MyService { data: 123 });
         let mut s = System::from(my_system_with_context);
-        s.data.run(&mut services);
+        s.data.run(&services);
         assert_eq!(services.get::<MyService>().unwrap().data, 0);
     }
 
+    fn my_system_with_conflict(_: Mut<MyService>, _: Const<MyService>) {}
+
+    #[test]
+    #[should_panic(expected = "MyService")]
+    fn conflicting_access() {
+        System::from(my_system_with_conflict);
+    }
+
+    fn my_system_with_option(mut service: Option<Mut<MyService>>, world: Option<Const<World>>) {
+        if let Some(service) = service.as_mut() {
+            service.data = if world.is_some() { 1 } else { 2 };
+        }
+    }
+
+    #[test]
+    fn optional_services() {
+        let mut services = Services::new();
+        let mut s = System::from(my_system_with_option);
+        s.data.run(&services);
+        services.add(MyService { data: 0 });
+        s.data.run(&services);
+        assert_eq!(services.get::<MyService>().unwrap().data, 2);
+        assert_eq!(s.data.access().len(), 2);
+    }
+
+    struct Health(u32);
+
+    struct Corpse;
+
+    fn my_system_with_commands(world: Const<World>, mut commands: Commands) {
+        for (entity, health) in world.query::<(Entity, &Health)>() {
+            if health.0 == 0 {
+                commands.despawn(entity);
+                commands.spawn((Corpse,));
+            }
+        }
+    }
+
+    #[test]
+    fn deferred_commands() {
+        let mut services = Services::new();
+        let mut world = World::new();
+        world.spawn(vec![(Health(0),), (Health(10),)]);
+        services.add(world);
+
+        let mut s = System::from(my_system_with_commands);
+        assert!(s.data.access().iter().all(|access| !access.mutable));
+        s.data.run(&services);
+        assert_eq!(services.get::<World>().unwrap().query::<(&Health,)>().count(), 2);
+
+        s.data.apply(&mut services);
+        let world = services.get::<World>().unwrap();
+        assert_eq!(world.query::<(&Health,)>().count(), 1);
+        assert_eq!(world.query::<(&Corpse,)>().count(), 1);
+    }
+
+    #[test]
+    fn system_access() {
+        let s = System::from(my_system_with_context);
+        let access = s.data.access();
+        assert_eq!(access.len(), 1);
+        assert!(access[0].mutable);
+    }
 }
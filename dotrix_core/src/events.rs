@@ -0,0 +1,138 @@
+use std::marker::PhantomData;
+
+use crate::ecs::Mut;
+
+/// Service implementing a typed channel of events between systems
+///
+/// Events are double buffered: an event sent in frame N stays readable until the end of frame
+/// N + 1. Each reading system should keep its own `EventReader` in the system context, so it
+/// sees each event exactly once. Channels are registered by `Dotrix::with_events` method.
+pub struct Events<T> {
+    /// Events sent during the previous frame
+    previous: Vec<T>,
+    /// Events sent during the current frame
+    current: Vec<T>,
+    /// Sequence number of the first event of the previous frame
+    previous_start: usize,
+    /// Sequence number of the first event of the current frame
+    current_start: usize,
+}
+
+impl<T> Events<T> {
+    /// Creates new empty channel
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+            current_start: 0,
+        }
+    }
+
+    /// Sends an event to the channel
+    pub fn send(&mut self, event: T) {
+        self.current.push(event);
+    }
+
+    /// Swaps buffers, dropping events of the previous frame. Should be called once per frame.
+    pub fn update(&mut self) {
+        self.previous_start = self.current_start;
+        self.current_start += self.current.len();
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    /// Returns number of readable events
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    /// Returns true if there are no readable events
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sequence number of the next sent event
+    fn end(&self) -> usize {
+        self.current_start + self.current.len()
+    }
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cursor of a system reading events of the channel
+pub struct EventReader<T> {
+    /// Sequence number of the next event to read
+    next: usize,
+    _phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> EventReader<T> {
+    /// Returns events, that were not read yet by this reader
+    pub fn iter<'a>(&mut self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let next = self.next.max(events.previous_start);
+        self.next = events.end();
+
+        let previous = events.previous
+            .get(next.saturating_sub(events.previous_start)..)
+            .unwrap_or(&[]);
+        let current = events.current
+            .get(next.saturating_sub(events.current_start)..)
+            .unwrap_or(&[]);
+
+        previous.iter().chain(current.iter())
+    }
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+/// System swapping buffers of the events channel
+pub fn events_update<T: Send + Sync + 'static>(mut events: Mut<Events<T>>) {
+    events.update();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ EventReader, Events };
+
+    #[test]
+    fn read_once() {
+        let mut events = Events::new();
+        let mut early = EventReader::default();
+        let mut late = EventReader::default();
+
+        // frame 1: `early` reader runs before the sender and `late` after it
+        assert_eq!(early.iter(&events).count(), 0);
+        events.send(1);
+        events.send(2);
+        assert_eq!(late.iter(&events).copied().collect::<Vec<_>>(), vec![1, 2]);
+
+        // frame 2
+        events.update();
+        assert_eq!(early.iter(&events).copied().collect::<Vec<_>>(), vec![1, 2]);
+        events.send(3);
+        assert_eq!(late.iter(&events).copied().collect::<Vec<_>>(), vec![3]);
+
+        // frame 3: events of frame 1 are dropped
+        events.update();
+        assert_eq!(events.len(), 1);
+        assert_eq!(early.iter(&events).copied().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(late.iter(&events).count(), 0);
+
+        // a reader, that missed a frame, sees only events still in buffers
+        let mut missed = EventReader::default();
+        events.update();
+        events.send(4);
+        assert_eq!(missed.iter(&events).copied().collect::<Vec<_>>(), vec![4]);
+    }
+}
@@ -9,6 +9,8 @@ pub struct Frame {
     fps: Option<u32>,
     delta: Duration,
     time: Duration,
+    fixed_delta: Duration,
+    alpha: f32,
 }
 
 impl Frame {
@@ -21,11 +23,17 @@ impl Frame {
             fps: None,
             delta: Duration::from_secs(0),
             time: Duration::from_secs(0),
+            fixed_delta: Duration::from_secs(0),
+            alpha: 0.0,
         }
     }
 
     pub fn next(&mut self) {
-        let now = Instant::now();
+        self.next_at(Instant::now());
+    }
+
+    /// Starts the next frame at the given time
+    pub fn next_at(&mut self, now: Instant) {
         if let Some(first) = self.first {
             self.time = now - first;
         } else {
@@ -63,6 +71,25 @@ impl Frame {
     pub fn delta(&self) -> Duration {
         self.delta
     }
+
+    /// Returns time step of the running `RunLevel::Fixed` systems
+    pub fn fixed_delta(&self) -> Duration {
+        self.fixed_delta
+    }
+
+    /// Returns interpolation factor between the last two fixed steps for rendering. If several
+    /// fixed frequencies are used, the factor is calculated for the lowest one
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    pub(crate) fn set_fixed_delta(&mut self, fixed_delta: Duration) {
+        self.fixed_delta = fixed_delta;
+    }
+
+    pub(crate) fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha;
+    }
 }
 
 impl Default for Frame {
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use dotrix_math::{Mat4, SquareMatrix};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ecs::{Entity, Mut},
+    renderer::{Model, Transform},
+    services::World,
+};
+
+/// Component linking an entity to its parent
+#[derive(Serialize, Deserialize)]
+pub struct Parent(pub Entity);
+
+/// Component listing children of an entity
+#[derive(Default, Serialize, Deserialize)]
+pub struct Children(pub Vec<Entity>);
+
+/// Component holding the transformation of an entity in the world space, calculated by the
+/// `transform_propagation` system from local transformations of the entity and its ancestors
+#[derive(Serialize, Deserialize)]
+pub struct GlobalTransform(pub Mat4);
+
+impl GlobalTransform {
+    /// Returns the transformation matrix
+    pub fn matrix(&self) -> &Mat4 {
+        &self.0
+    }
+}
+
+impl Default for GlobalTransform {
+    fn default() -> Self {
+        Self(Mat4::identity())
+    }
+}
+
+/// Attaches the child entity to the parent one, detaching it from the previous parent. Adds
+/// `GlobalTransform` component to the child if it does not have one. Returns false if any of
+/// entities does not exist or if the parent is the child itself or its descendant.
+pub fn attach(world: &mut World, child: Entity, parent: Entity) -> bool {
+    if !world.exists(child) || !world.exists(parent) {
+        return false;
+    }
+
+    let mut ancestor = Some(parent);
+    while let Some(entity) = ancestor {
+        if entity == child {
+            return false;
+        }
+        ancestor = world.get::<Parent>(entity).map(|p| p.0);
+    }
+
+    detach(world, child);
+
+    world.insert(child, Parent(parent));
+    if world.get::<GlobalTransform>(child).is_none() {
+        world.insert(child, GlobalTransform::default());
+    }
+
+    if let Some(children) = world.get_mut::<Children>(parent) {
+        children.0.push(child);
+    } else {
+        world.insert(parent, Children(vec![child]));
+    }
+    true
+}
+
+/// Detaches the child entity from its parent. Returns false if the entity had no parent.
+pub fn detach(world: &mut World, child: Entity) -> bool {
+    if let Some(Parent(parent)) = world.remove::<Parent>(child) {
+        if let Some(children) = world.get_mut::<Children>(parent) {
+            children.0.retain(|c| *c != child);
+        }
+        true
+    } else {
+        false
+    }
+}
+
+/// Despawns the entity with all its descendants
+pub fn despawn_recursive(world: &mut World, entity: Entity) -> bool {
+    detach(world, entity);
+    despawn_tree(world, entity)
+}
+
+fn despawn_tree(world: &mut World, entity: Entity) -> bool {
+    if let Some(Children(children)) = world.remove::<Children>(entity) {
+        for child in children {
+            despawn_tree(world, child);
+        }
+    }
+    world.despawn(entity)
+}
+
+/// Node of the hierarchy used during the propagation
+struct Node {
+    local: Mat4,
+    parent: Option<Entity>,
+}
+
+/// System calculating `GlobalTransform` components from the hierarchy of entities
+///
+/// Local transformation of an entity is taken from its `Transform` component or from the
+/// `transform` field of its `Model`. Entities without any of them are treated as identity
+/// transformations, so an empty entity can be used as a pivot. The system should run after
+/// systems moving the entities and before the `world_renderer`.
+pub fn transform_propagation(world: Mut<World>) {
+    let mut nodes = HashMap::new();
+    let query = world.query::<(Entity, Option<&Transform>, Option<&Model>, Option<&Parent>)>();
+    for (entity, transform, model, parent) in query {
+        let local = transform
+            .or_else(|| model.map(|m| &m.transform))
+            .map(|t| t.matrix())
+            .unwrap_or_else(Mat4::identity);
+        nodes.insert(entity, Node { local, parent: parent.map(|p| p.0) });
+    }
+
+    let mut cache = HashMap::new();
+    for (entity, global_transform) in world.query::<(Entity, &mut GlobalTransform)>() {
+        global_transform.0 = global(entity, &nodes, &mut cache, nodes.len());
+    }
+}
+
+/// Calculates the global transformation of the entity, caching results for its ancestors.
+/// `depth` limits the recursion, if the hierarchy was broken by a loop.
+fn global(
+    entity: Entity,
+    nodes: &HashMap<Entity, Node>,
+    cache: &mut HashMap<Entity, Mat4>,
+    depth: usize,
+) -> Mat4 {
+    if let Some(matrix) = cache.get(&entity) {
+        return *matrix;
+    }
+
+    let node = match nodes.get(&entity) {
+        Some(node) => node,
+        None => return Mat4::identity(),
+    };
+
+    let matrix = match node.parent {
+        Some(parent) if depth > 0 && nodes.contains_key(&parent) => {
+            global(parent, nodes, cache, depth - 1) * node.local
+        },
+        _ => node.local,
+    };
+
+    cache.insert(entity, matrix);
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use dotrix_math::{Mat4, Vec3};
+
+    use crate::{
+        application::services::Services,
+        ecs::System,
+        renderer::Transform,
+        services::World,
+    };
+    use super::*;
+
+    #[test]
+    fn propagation() {
+        let mut world = World::new();
+        let rig = world.spawn(Some((Transform::from_translation(Vec3::new(1.0, 0.0, 0.0)),)))[0];
+        let arm = world.spawn(Some((Transform::from_translation(Vec3::new(0.0, 2.0, 0.0)),)))[0];
+        let hand = world.spawn(Some((Transform::from_translation(Vec3::new(0.0, 0.0, 3.0)),)))[0];
+
+        assert!(attach(&mut world, arm, rig));
+        assert!(attach(&mut world, hand, arm));
+        assert!(!attach(&mut world, rig, hand));
+        assert_eq!(world.get::<Children>(rig).unwrap().0, vec![arm]);
+
+        let mut services = Services::new();
+        services.add(world);
+        let (mut system, _, _) = System::from(transform_propagation).tuple();
+        system.run(&services);
+
+        let world = services.get_mut::<World>().unwrap();
+        let expected = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(world.get::<GlobalTransform>(hand).unwrap().0, expected);
+
+        assert!(despawn_recursive(world, arm));
+        assert!(!world.exists(hand));
+        assert!(world.get::<Children>(rig).unwrap().0.is_empty());
+    }
+}
@@ -3,17 +3,35 @@ pub mod animation;
 pub mod assets;
 mod camera;
 pub mod ecs;
+pub mod events;
 mod frame;
+pub mod hierarchy;
 pub mod input;
+pub mod profiler;
 pub mod renderer;
 mod scheduler;
+pub mod state;
 mod world;
 
-pub use application::{ Application, Service };
+pub use application::{
+    Application,
+    Clock,
+    Extension,
+    Headless,
+    ManualClock,
+    Service,
+    SystemClock,
+};
+pub use world::SceneError;
 
 pub mod components {
     pub use crate::{
         animation::Animator,
+        hierarchy::{
+            Children,
+            GlobalTransform,
+            Parent,
+        },
         renderer::{
             Light,
             Model,
@@ -24,11 +42,15 @@ pub mod components {
 
 pub mod services {
     pub use crate::{
+        application::AppControl,
         assets::Assets,
         camera::Camera,
+        events::Events,
         input::Input,
         frame::Frame,
+        profiler::Profiler,
         renderer::Renderer,
+        state::State,
         world::World,
     };
 }
@@ -41,10 +63,14 @@ pub mod systems {
         renderer::overlay_update,
         animation::skeletal_animation,
         camera::camera_control,
+        events::events_update,
+        hierarchy::transform_propagation,
     };
 }
 
 use ecs::System;
+use events::{Events, events_update};
+use state::{State, StateLabel};
 
 pub struct Dotrix {
     app: Option<Application>,
@@ -80,6 +106,33 @@ impl Dotrix {
         self
     }
 
+    /// Adds systems and services of the extension
+    pub fn with<E: Extension>(&mut self, extension: E) -> &mut Self {
+        self.app.as_mut().unwrap().add_extension(extension);
+        self
+    }
+
+    /// Registers a channel of events of the type and the system updating it every frame
+    pub fn with_events<T: Service>(&mut self) -> &mut Self
+    {
+        let app = self.app.as_mut().unwrap();
+        app.add_service(Events::<T>::new());
+        app.add_system(System::from(events_update::<T>));
+        self
+    }
+
+    /// Turns the application into the headless one, which runs frames on demand without a window
+    pub fn headless(&mut self) -> Headless {
+        self.app.take().unwrap().headless()
+    }
+
+    /// Registers a service holding the state of the application
+    pub fn with_state<S: StateLabel>(&mut self, initial: S) -> &mut Self
+    {
+        self.app.as_mut().unwrap().add_service(State::new(initial));
+        self
+    }
+
     /// Run the application
     pub fn run(&mut self) {
         let app = self.app.take().unwrap();
@@ -0,0 +1,399 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::Write,
+    time::{ Duration, Instant },
+};
+
+use serde_json::{ json, Value };
+
+/// Default number of frames kept in the history of the profiler
+const HISTORY: usize = 120;
+
+/// Run level of profiled systems
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Startup,
+    Standard,
+    /// Fixed run level with the frequency in Hz
+    Fixed(u32),
+    Render,
+    Shutdown,
+    /// Systems running on enter to a state of the type
+    Enter(&'static str),
+    /// Systems running on exit from a state of the type
+    Exit(&'static str),
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Startup => write!(f, "Startup"),
+            Level::Standard => write!(f, "Standard"),
+            Level::Fixed(hz) => write!(f, "Fixed({} Hz)", hz),
+            Level::Render => write!(f, "Render"),
+            Level::Shutdown => write!(f, "Shutdown"),
+            Level::Enter(state) => write!(f, "OnEnter({})", state),
+            Level::Exit(state) => write!(f, "OnExit({})", state),
+        }
+    }
+}
+
+/// Time of a single run of a system
+#[derive(Debug, Clone)]
+pub struct Span {
+    /// Name of the system
+    pub name: &'static str,
+    pub level: Level,
+    pub start: Instant,
+    pub duration: Duration,
+    /// Thread the system ran on: 0 for the main thread, 1 and more for the worker threads
+    pub thread: usize,
+}
+
+/// Time of a single run of all systems of a run level, including sync points
+#[derive(Debug, Clone)]
+pub struct LevelSpan {
+    pub level: Level,
+    pub start: Instant,
+    pub duration: Duration,
+}
+
+/// Timings of a single frame
+#[derive(Debug, Clone)]
+pub struct FrameProfile {
+    /// Sequence number of the frame
+    pub number: u64,
+    pub start: Instant,
+    pub duration: Duration,
+    /// Run levels in the order of execution, fixed levels could run several times per frame
+    pub levels: Vec<LevelSpan>,
+    /// Systems in the order of completion
+    pub systems: Vec<Span>,
+}
+
+impl FrameProfile {
+    fn new(number: u64, start: Instant) -> Self {
+        Self {
+            number,
+            start,
+            duration: Duration::from_secs(0),
+            levels: Vec::new(),
+            systems: Vec::new(),
+        }
+    }
+
+    /// Returns total time of the run level in the frame
+    pub fn level_time(&self, level: Level) -> Option<Duration> {
+        sum(self.levels.iter().filter(|l| l.level == level).map(|l| l.duration))
+    }
+
+    /// Returns total time of the system in the frame
+    pub fn system_time(&self, name: &str) -> Option<Duration> {
+        sum(self.systems.iter().filter(|s| s.name == name).map(|s| s.duration))
+    }
+}
+
+/// Breakdown of the frame by run levels and systems, slowest systems first
+impl fmt::Display for FrameProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Frame #{}: {}", self.number, ms(self.duration))?;
+        let mut levels = Vec::new();
+        for span in self.levels.iter() {
+            if !levels.contains(&span.level) {
+                levels.push(span.level);
+            }
+        }
+        for level in levels {
+            let time = self.level_time(level).unwrap_or_default();
+            writeln!(f, "  {}: {}", level, ms(time))?;
+
+            let mut systems: Vec<(&'static str, Duration)> = Vec::new();
+            for span in self.systems.iter().filter(|s| s.level == level) {
+                match systems.iter_mut().find(|(name, _)| *name == span.name) {
+                    Some((_, duration)) => *duration += span.duration,
+                    None => systems.push((span.name, span.duration)),
+                }
+            }
+            systems.sort_by_key(|(_, duration)| std::cmp::Reverse(*duration));
+            for (name, duration) in systems {
+                writeln!(f, "    {}: {}", name, ms(duration))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Statistics of a duration over the history of frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    /// Number of frames, in which the measured code ran
+    pub frames: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// 99th percentile
+    pub p99: Duration,
+}
+
+impl Stats {
+    fn from(mut samples: Vec<Duration>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let frames = samples.len();
+        let total: Duration = samples.iter().sum();
+        let p99 = (frames * 99).div_ceil(100);
+        Some(Self {
+            frames,
+            min: samples[0],
+            avg: total / frames as u32,
+            max: samples[frames - 1],
+            p99: samples[p99 - 1],
+        })
+    }
+}
+
+/// Service measuring wall-clock time of systems and run levels
+///
+/// Timings are recorded by the scheduler, when the service is added to the application and
+/// enabled. The profiler keeps the history of the last frames to calculate rolling statistics.
+pub struct Profiler {
+    enabled: bool,
+    history: usize,
+    /// Time, from which timestamps of the trace are counted
+    origin: Instant,
+    counter: u64,
+    current: Option<FrameProfile>,
+    frames: VecDeque<FrameProfile>,
+}
+
+impl Profiler {
+    /// Creates the profiler keeping the number of frames in the history
+    pub fn new(history: usize) -> Self {
+        assert!(history > 0, "History of the profiler must contain at least one frame");
+        Self {
+            enabled: true,
+            history,
+            origin: Instant::now(),
+            counter: 0,
+            current: None,
+            frames: VecDeque::with_capacity(history),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables recording of timings, the history is kept
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Drops the history
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.frames.clear();
+    }
+
+    /// Finishes the current frame and starts the next one. Called by the application at the
+    /// beginning of each frame.
+    pub fn next_frame(&mut self) {
+        let now = Instant::now();
+        if let Some(mut frame) = self.current.take() {
+            frame.duration = now.saturating_duration_since(frame.start);
+            if self.frames.len() == self.history {
+                self.frames.pop_front();
+            }
+            self.frames.push_back(frame);
+        }
+        if self.enabled {
+            self.start(now);
+        }
+    }
+
+    /// Returns finished frames from the oldest to the latest
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> {
+        self.frames.iter()
+    }
+
+    /// Returns the latest finished frame
+    pub fn last_frame(&self) -> Option<&FrameProfile> {
+        self.frames.back()
+    }
+
+    /// Returns statistics of the frame time
+    pub fn frame_stats(&self) -> Option<Stats> {
+        Stats::from(self.frames.iter().map(|f| f.duration).collect())
+    }
+
+    /// Returns statistics of the time of the run level per frame
+    pub fn level_stats(&self, level: Level) -> Option<Stats> {
+        Stats::from(self.frames.iter().filter_map(|f| f.level_time(level)).collect())
+    }
+
+    /// Returns statistics of the time of the system per frame
+    pub fn system_stats(&self, name: &str) -> Option<Stats> {
+        Stats::from(self.frames.iter().filter_map(|f| f.system_time(name)).collect())
+    }
+
+    /// Returns statistics of all systems from the history, slowest on average first
+    pub fn systems(&self) -> Vec<(&'static str, Stats)> {
+        let mut names = Vec::new();
+        for span in self.frames.iter().flat_map(|f| f.systems.iter()) {
+            if !names.contains(&span.name) {
+                names.push(span.name);
+            }
+        }
+        let mut result = names.into_iter()
+            .filter_map(|name| self.system_stats(name).map(|stats| (name, stats)))
+            .collect::<Vec<_>>();
+        result.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.avg));
+        result
+    }
+
+    /// Writes the history in the Chrome trace event format, that can be opened by
+    /// `chrome://tracing` or Perfetto
+    pub fn write_chrome_trace(&self, writer: impl Write) -> serde_json::Result<()> {
+        let mut events = Vec::new();
+        for frame in self.frames.iter() {
+            let name = format!("Frame #{}", frame.number);
+            events.push(self.event(&name, "Frame", frame.start, frame.duration, 0));
+            for span in frame.levels.iter() {
+                let name = span.level.to_string();
+                events.push(self.event(&name, "RunLevel", span.start, span.duration, 0));
+            }
+            for span in frame.systems.iter() {
+                let category = span.level.to_string();
+                let thread = span.thread;
+                events.push(self.event(span.name, &category, span.start, span.duration, thread));
+            }
+        }
+        serde_json::to_writer(writer, &json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+        }))
+    }
+
+    /// Returns the complete event of the Chrome trace, timestamps are in microseconds
+    fn event(
+        &self,
+        name: &str,
+        category: &str,
+        start: Instant,
+        duration: Duration,
+        thread: usize,
+    ) -> Value {
+        json!({
+            "name": name,
+            "cat": category,
+            "ph": "X",
+            "ts": start.saturating_duration_since(self.origin).as_secs_f64() * 1_000_000.0,
+            "dur": duration.as_secs_f64() * 1_000_000.0,
+            "pid": 1,
+            "tid": thread,
+        })
+    }
+
+    fn start(&mut self, now: Instant) {
+        self.counter += 1;
+        self.current = Some(FrameProfile::new(self.counter, now));
+    }
+
+    /// Records timings of the run level and its systems
+    pub(crate) fn record(&mut self, level: LevelSpan, systems: Vec<Span>) {
+        if self.current.is_none() {
+            self.start(level.start);
+        }
+        let frame = self.current.as_mut().expect("Current frame must be started");
+        frame.levels.push(level);
+        frame.systems.extend(systems);
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new(HISTORY)
+    }
+}
+
+/// Returns index of the current thread for profiling
+pub(crate) fn thread() -> usize {
+    rayon::current_thread_index().map(|index| index + 1).unwrap_or(0)
+}
+
+fn sum(durations: impl Iterator<Item = Duration>) -> Option<Duration> {
+    durations.fold(None, |total, duration| Some(total.unwrap_or_default() + duration))
+}
+
+fn ms(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        application::services::Services,
+        ecs::{ Const, RunLevel, System },
+        scheduler::Scheduler,
+    };
+    use super::{ Level, Profiler };
+
+    #[derive(Default)]
+    struct Sleep(u64);
+
+    fn slow(sleep: Const<Sleep>) {
+        std::thread::sleep(Duration::from_millis(sleep.0));
+    }
+
+    fn fast(_sleep: Const<Sleep>) {}
+
+    #[test]
+    fn timings() {
+        let mut services = Services::new();
+        services.add(Sleep(2));
+        services.add(Profiler::new(4));
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(System::from(slow));
+        scheduler.add(System::from(fast));
+        scheduler.add(System::from(fast).with(RunLevel::Render));
+
+        for _ in 0..6 {
+            services.get_mut::<Profiler>().unwrap().next_frame();
+            scheduler.run_standard(&mut services);
+            scheduler.run_render(&mut services);
+        }
+        services.get_mut::<Profiler>().unwrap().next_frame();
+
+        let profiler = services.get::<Profiler>().unwrap();
+        assert_eq!(profiler.frames().count(), 4);
+        assert_eq!(profiler.last_frame().unwrap().number, 6);
+
+        let slow = profiler.systems()[0];
+        assert!(slow.0.ends_with("slow"));
+        assert_eq!(slow.1.frames, 4);
+        assert!(slow.1.min >= Duration::from_millis(2));
+        assert!(slow.1.min <= slow.1.avg && slow.1.avg <= slow.1.p99 && slow.1.p99 <= slow.1.max);
+
+        let standard = profiler.level_stats(Level::Standard).unwrap();
+        assert!(standard.min >= slow.1.min);
+        assert!(profiler.level_stats(Level::Render).is_some());
+        assert!(profiler.level_stats(Level::Fixed(60)).is_none());
+
+        let breakdown = profiler.last_frame().unwrap().to_string();
+        assert!(breakdown.starts_with("Frame #6"));
+        assert!(breakdown.contains("Render"));
+
+        let mut trace = Vec::new();
+        profiler.write_chrome_trace(&mut trace).unwrap();
+        let trace: serde_json::Value = serde_json::from_slice(&trace).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 4 * (1 + 2 + 3));
+        assert!(events.iter().all(|e| e["ph"] == "X"));
+    }
+}
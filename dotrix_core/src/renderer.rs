@@ -21,9 +21,12 @@ use wgpu::util::DeviceExt;
 use dotrix_math::{Mat4, Deg, perspective};
 
 use crate::{
+    application::{ Application, Extension },
     assets::Id,
-    ecs::{ Const, Mut, Context },
-    services::{ Assets, Camera, World },
+    components::GlobalTransform,
+    ecs::{ After, Const, Mut, Context, Label, RunLevel, System },
+    hierarchy::transform_propagation,
+    services::{ Assets, Camera, Frame, World },
 };
 
 pub struct Renderer {
@@ -226,6 +229,56 @@ pub struct Pipelines {
     overlay: Id<Pipeline>,
 }
 
+/// Extension adding the `world_renderer` system, the `transform_propagation` system and default
+/// `Assets`, `Camera`, `Frame` and `World` services, if they were not added yet
+///
+/// Both systems run at `RunLevel::Render`, so transformations are propagated after all standard
+/// and fixed systems moved the entities. The systems are labeled `transform_propagation` and
+/// `world_renderer`, so other render systems can be ordered against them. Headless applications
+/// do not run the render level and should add `transform_propagation` themselves, if they need
+/// `GlobalTransform` components.
+pub struct DefaultRenderer;
+
+impl Extension for DefaultRenderer {
+    fn add_to(self, app: &mut Application) {
+        app.add_system(
+            System::from(transform_propagation)
+                .with(RunLevel::Render)
+                .with(Label("transform_propagation"))
+        );
+        app.add_system(
+            System::from(world_renderer)
+                .with(RunLevel::Render)
+                .with(Label("world_renderer"))
+                .with(After("transform_propagation"))
+        );
+        app.add_default_service(Assets::new());
+        app.add_default_service(Camera::default());
+        app.add_default_service(Frame::new());
+        app.add_default_service(World::new());
+    }
+}
+
+/// Adds hooks, that release GPU buffers of `Model`, `SkyBox` and `Widget` components removed
+/// from the `World`
+fn release_buffers_on_remove(world: &mut World) {
+    world.on_remove::<Model, _>(|world, entity, _| {
+        if let Some(model) = world.get::<Model>(entity) {
+            model.destroy_buffers();
+        }
+    });
+    world.on_remove::<SkyBox, _>(|world, entity, _| {
+        if let Some(skybox) = world.get::<SkyBox>(entity) {
+            skybox.destroy_buffers();
+        }
+    });
+    world.on_remove::<Widget, _>(|world, entity, _| {
+        if let Some(widget) = world.get::<Widget>(entity) {
+            widget.destroy_buffers();
+        }
+    });
+}
+
 #[derive(Default)]
 pub struct WorldRenderer {
     lights_buffer: Option<wgpu::Buffer>,
@@ -234,14 +287,19 @@ pub struct WorldRenderer {
     pipelines: Option<Pipelines>,
 }
 
+/// Renders models, skyboxes and overlays
+///
+/// On the first run the system adds `on_remove` hooks to the `World` service, that release GPU
+/// buffers of removed `Model`, `SkyBox` and `Widget` components
 pub fn world_renderer(
     mut ctx: Context<WorldRenderer>,
     mut renderer: Mut<Renderer>,
     mut assets: Mut<Assets>,
     camera: Const<Camera>,
-    world: Const<World>
+    mut world: Mut<World>
 ) {
     if ctx.pipelines.is_none() {
+        release_buffers_on_remove(&mut world);
         let skybox = renderer.add_skybox_pipeline();
         let static_model = renderer.add_static_model_pipeline();
         let skinned_model = renderer.add_skinned_model_pipeline();
@@ -350,8 +408,8 @@ pub fn world_renderer(
     }
 
     // render static models
-    let query = world.query::<(&mut Model,)>();
-    for (model,) in query {
+    let query = world.query::<(&mut Model, Option<&GlobalTransform>)>();
+    for (model, global_transform) in query {
         if model.pipeline.is_null() {
             let pipelines = ctx.pipelines.as_ref().unwrap();
             model.pipeline = if !model.skin.is_null() {
@@ -364,7 +422,19 @@ pub fn world_renderer(
         let proj_view_buffer = ctx.proj_view_buffer.as_ref().unwrap();
         let lights_buffer = ctx.lights_buffer.as_ref().unwrap();
 
-        model.load(&renderer, &mut assets, pipeline, sampler, proj_view_buffer, lights_buffer);
+        let transform = global_transform
+            .map(|t| t.0)
+            .unwrap_or_else(|| model.transform.matrix());
+
+        model.load(
+            &renderer,
+            &mut assets,
+            pipeline,
+            sampler,
+            proj_view_buffer,
+            lights_buffer,
+            &transform,
+        );
         model.draw(&assets, &mut encoder, pipeline, frame, depth_buffer);
     }
 
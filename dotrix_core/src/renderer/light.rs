@@ -1,10 +1,11 @@
 use dotrix_math::Vec4;
+use serde::{Deserialize, Serialize};
 
 const MAX_LIGHTS: usize = 10;
 
 /// Component to be added to entities
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub struct Light {
     pub position: Vec4,
     pub color: Vec4,
@@ -1,3 +1,6 @@
+use dotrix_math::Mat4;
+use serde::{Deserialize, Serialize};
+
 use crate::{
     assets::{ Id, Mesh, Skin, Pose, Texture },
     services::{ Assets, Renderer },
@@ -9,21 +12,39 @@ use super::transform::Transform;
 pub struct Buffers {
     bind_group: wgpu::BindGroup,
     transform: wgpu::Buffer,
+    /// Revisions of the mesh, the texture and the skin, that the bind group was created for
+    revisions: [u64; 3],
 }
 
-#[derive(Default)]
+/// Model component. GPU buffers, pose and pipeline are not serialized, they are restored by the
+/// renderer
+#[derive(Default, Serialize, Deserialize)]
 pub struct Model {
     pub mesh: Id<Mesh>,
     pub texture: Id<Texture>,
+    #[serde(default)]
     pub transform: Transform,
+    #[serde(default)]
     pub skin: Id<Skin>,
+    #[serde(skip)]
     pub pose: Option<Pose>,
+    #[serde(skip)]
     pub buffers: Option<Buffers>,
+    #[serde(skip)]
     pub pipeline: Id<Pipeline>,
 }
 
 impl Model {
 
+    /// Returns revisions of the mesh, the texture and the skin
+    fn revisions(&self, assets: &Assets) -> [u64; 3] {
+        [
+            assets.revision_of(self.mesh),
+            assets.revision_of(self.texture),
+            assets.revision_of(self.skin),
+        ]
+    }
+
     /// Returns loaded assets if they are all ready
     fn get_assets<'a>(
         &self,
@@ -58,6 +79,7 @@ impl Model {
     }
 
     /// Initialize model specific buffers, should be called just once by renderer
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn load(
         &mut self,
         renderer: &Renderer,
@@ -66,14 +88,20 @@ impl Model {
         sampler: &wgpu::Sampler,
         proj_view: &wgpu::Buffer,
         lights_buffer: &wgpu::Buffer,
+        transform: &Mat4,
     ) {
         use wgpu::util::DeviceExt;
 
         let device = &renderer.device;
         let queue = &renderer.queue;
 
-        let transform_matrix = self.transform.matrix();
-        let model_transform = AsRef::<[f32; 16]>::as_ref(&transform_matrix);
+        let model_transform = AsRef::<[f32; 16]>::as_ref(transform);
+
+        // the assets could be reloaded, so the bind group must be recreated
+        let revisions = self.revisions(assets);
+        if self.buffers.as_ref().map(|b| b.revisions != revisions).unwrap_or(false) {
+            self.release();
+        }
 
         if let Ok((_, texture, skin)) = self.get_assets(assets, device, queue) {
             if let Some(buffers) = self.buffers.as_ref() {
@@ -132,6 +160,7 @@ impl Model {
                     Buffers {
                         bind_group,
                         transform,
+                        revisions,
                     }
                 )
             }
@@ -194,4 +223,23 @@ impl Model {
             }
         }
     }
+
+    /// Destroys GPU buffers of the model and its pose and drops them, so they are created again,
+    /// if the model is rendered after it
+    pub fn release(&mut self) {
+        self.destroy_buffers();
+        self.buffers = None;
+        self.pose = None;
+    }
+
+    /// Destroys GPU buffers of the model and its pose, the model must not be rendered after it.
+    /// Renderer calls it for models removed from the World
+    pub(super) fn destroy_buffers(&self) {
+        if let Some(buffers) = self.buffers.as_ref() {
+            buffers.transform.destroy();
+        }
+        if let Some(pose) = self.pose.as_ref() {
+            pose.buffer.destroy();
+        }
+    }
 }
@@ -103,8 +103,13 @@ impl dyn Provider {
 pub fn overlay_update(
     mut assets: Mut<Assets>,
     input: Const<Input>,
-    mut renderer: Mut<Renderer>
+    renderer: Option<Mut<Renderer>>
 ) {
+    // Headless applications have no renderer
+    let mut renderer = match renderer {
+        Some(renderer) => renderer,
+        None => return,
+    };
     let (width, height) = renderer.display_size();
     let scale_factor = renderer.scale_factor();
     for overlay in &mut renderer.overlay {
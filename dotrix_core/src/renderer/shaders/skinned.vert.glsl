@@ -15,10 +15,12 @@ layout(set = 0, binding = 0) uniform Renderer {
     mat4 u_ProjView;
 };
 
+// global transformation of the entity (GlobalTransform or Model::transform)
 layout(set = 0, binding = 1) uniform Model {
     mat4 u_Model;
 };
 
+// joint matrices in the model space
 layout(set = 0, binding = 2) uniform JointMatrices {
     mat4 u_JointMatrix[MAX_JOINTS];
 };
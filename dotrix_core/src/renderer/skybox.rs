@@ -12,6 +12,8 @@ pub struct Buffers {
     pub indices: wgpu::Buffer,
     pub proj_view: wgpu::Buffer,
     pub indices_count: u32,
+    /// Revisions of the faces, that the buffers were created for
+    pub revisions: [u64; 6],
 }
 
 #[derive(Default)]
@@ -36,6 +38,15 @@ impl SkyBox {
         Some(faces)
     }
 
+    /// Returns revisions of the faces
+    fn revisions(&self, assets: &Assets) -> [u64; 6] {
+        let mut revisions = [0; 6];
+        for (revision, texture_id) in revisions.iter_mut().zip(self.primary_texture.iter()) {
+            *revision = assets.revision_of(*texture_id);
+        }
+        revisions
+    }
+
     pub fn load(
         &mut self,
         assets: &Assets,
@@ -48,6 +59,11 @@ impl SkyBox {
         use wgpu::util::DeviceExt;
 
         let proj_view_slice = AsRef::<[f32; 16]>::as_ref(proj_view_matrix);
+        // faces could be reloaded, so the buffers must be recreated
+        let revisions = self.revisions(assets);
+        if self.buffers.as_ref().map(|b| b.revisions != revisions).unwrap_or(false) {
+            self.release();
+        }
         if let Some(buffers) = self.buffers.as_ref() {
             queue.write_buffer(&buffers.proj_view, 0, bytemuck::cast_slice(proj_view_slice));
         } else {
@@ -145,6 +161,7 @@ impl SkyBox {
                     vertices,
                     indices,
                     indices_count: cube.indices_count(),
+                    revisions,
                 })
             } else {
                 None
@@ -187,4 +204,21 @@ impl SkyBox {
             rpass.draw_indexed(0..buffers.indices_count, 0, 0..1);
         }
     }
+
+    /// Destroys GPU buffers of the skybox and drops them, so they are created again, if the
+    /// skybox is rendered after it
+    pub fn release(&mut self) {
+        self.destroy_buffers();
+        self.buffers = None;
+    }
+
+    /// Destroys GPU buffers of the skybox, the skybox must not be rendered after it.
+    /// Renderer calls it for skyboxes removed from the World
+    pub(super) fn destroy_buffers(&self) {
+        if let Some(buffers) = self.buffers.as_ref() {
+            buffers.vertices.destroy();
+            buffers.indices.destroy();
+            buffers.proj_view.destroy();
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use dotrix_math::{Mat4, Vec3, Quat, Rotation3, Rad};
+use serde::{Deserialize, Serialize};
 
 #[derive(Default)]
 pub struct TransformBuilder {
@@ -33,7 +34,7 @@ impl TransformBuilder {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Transform {
     pub translate: Vec3,
     pub rotate: Quat,
@@ -172,6 +172,25 @@ impl Widget {
             }
         }
     }
+
+    /// Destroys GPU buffers of the widget and drops them, so they are created again, if the
+    /// widget is rendered after it
+    pub fn release(&mut self) {
+        self.destroy_buffers();
+        self.buffers = None;
+    }
+
+    /// Destroys GPU buffers of the widget, the widget must not be rendered after it.
+    /// Renderer calls it for widgets removed from the World
+    pub(super) fn destroy_buffers(&self) {
+        if let Some(buffers) = self.buffers.as_ref() {
+            buffers.vertices_buffer.destroy();
+            if let Some(indices_buffer) = buffers.indices_buffer.as_ref() {
+                indices_buffer.destroy();
+            }
+            buffers.screen_size.destroy();
+        }
+    }
 }
 
 impl Default for Widget {
@@ -1,47 +1,541 @@
+use std::{
+    any::Any,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use rayon::prelude::*;
+
 use crate::{
     application::services::Services,
-    ecs::{RunLevel, System, Systemized},
+    ecs::{Access, Order, RunLevel, System, Systemized},
+    frame::Frame,
+    profiler::{self, Level, LevelSpan, Profiler, Span},
+    state::{Driver, Hook, Transition},
 };
 
+/// Maximal number of fixed steps per frame, the rest of accumulated time is dropped
+const MAX_FIXED_STEPS: u32 = 8;
+
 pub struct Scheduler {
-    render: Vec<Box<dyn Systemized>>,
-    standard: Vec<Box<dyn Systemized>>,
-    startup: Vec<Box<dyn Systemized>>,
+    render: Stage,
+    standard: Stage,
+    startup: Stage,
+    shutdown: Stage,
+    /// Fixed run levels sorted by frequency from the highest to the lowest
+    fixed: Vec<Fixed>,
+    /// Transition stages grouped by types of states
+    transitions: Vec<Transitions>,
 }
 
 impl Scheduler {
     pub fn new() -> Self {
         Self {
-            render: Vec::new(),
-            standard: Vec::new(),
-            startup: Vec::new(),
+            render: Stage::new(Level::Render),
+            standard: Stage::new(Level::Standard),
+            startup: Stage::new(Level::Startup),
+            shutdown: Stage::new(Level::Shutdown),
+            fixed: Vec::new(),
+            transitions: Vec::new(),
         }
     }
 
     pub fn add(&mut self, system: System) {
-        let (data, run_level) = system.tuple();
+        let (data, run_level, order) = system.tuple();
         match run_level {
-            RunLevel::Render => self.render.push(data),
-            RunLevel::Standard => self.standard.push(data),
-            RunLevel::Startup => self.startup.push(data),
+            RunLevel::Render => self.render.add(data, order),
+            RunLevel::Standard => self.standard.add(data, order),
+            RunLevel::Startup => self.startup.add(data, order),
+            RunLevel::Shutdown => self.shutdown.add(data, order),
+            RunLevel::Fixed(hz) => self.fixed_stage(hz).add(data, order),
+            RunLevel::Transition(transition) => self.transition_stage(transition).add(data, order),
         };
     }
 
-    pub fn run_render(&mut self, services: &mut Services) {
-        for system in &mut self.render {
-            system.run(services);
+    fn transition_stage(&mut self, transition: Transition) -> &mut Stage {
+        let Transition { hook, state, driver } = transition;
+        let index = match self.transitions.iter().position(|t| t.driver.type_id == driver.type_id) {
+            Some(index) => index,
+            None => {
+                self.transitions.push(Transitions::new(driver));
+                self.transitions.len() - 1
+            }
+        };
+        self.transitions[index].stage(hook, state)
+    }
+
+    /// Applies pending transitions of states running `OnExit` systems of previous states and
+    /// `OnEnter` systems of new ones
+    pub fn run_transitions(&mut self, services: &mut Services) {
+        for transitions in self.transitions.iter_mut() {
+            if let Some((previous, next)) = (transitions.driver.transit)(services) {
+                if let Some(previous) = previous {
+                    transitions.run(Hook::Exit, previous.as_ref(), services);
+                }
+                transitions.run(Hook::Enter, next.as_ref(), services);
+            }
         }
     }
 
-    pub fn run_standard(&mut self, services: &mut Services) {
-        for system in &mut self.standard {
-            system.run(services);
+    fn fixed_stage(&mut self, hz: u32) -> &mut Stage {
+        assert!(hz > 0, "Frequency of the fixed run level must be greater than zero");
+        let index = match self.fixed.iter().position(|fixed| fixed.hz <= hz) {
+            Some(index) if self.fixed[index].hz == hz => index,
+            Some(index) => {
+                self.fixed.insert(index, Fixed::new(hz));
+                index
+            },
+            None => {
+                self.fixed.push(Fixed::new(hz));
+                self.fixed.len() - 1
+            }
+        };
+        &mut self.fixed[index].stage
+    }
+
+    /// Accumulates frame time and runs fixed systems for each complete time step
+    pub fn run_fixed(&mut self, services: &mut Services, delta: Duration) {
+        for fixed in self.fixed.iter_mut() {
+            let max = fixed.step * MAX_FIXED_STEPS;
+            fixed.accumulator += delta;
+            if fixed.accumulator > max {
+                fixed.accumulator = max;
+            }
+
+            while fixed.accumulator >= fixed.step {
+                if let Some(frame) = services.get_mut::<Frame>() {
+                    frame.set_fixed_delta(fixed.step);
+                }
+                fixed.stage.run(services);
+                fixed.accumulator -= fixed.step;
+            }
+
+            if let Some(frame) = services.get_mut::<Frame>() {
+                frame.set_alpha(fixed.accumulator.as_secs_f32() / fixed.step.as_secs_f32());
+            }
         }
     }
 
+    pub fn run_render(&mut self, services: &mut Services) {
+        self.render.run(services);
+    }
+
+    pub fn run_standard(&mut self, services: &mut Services) {
+        self.standard.run(services);
+    }
+
     pub fn run_startup(&mut self, services: &mut Services) {
-        for system in &mut self.startup {
-            system.run(services);
+        self.startup.run(services);
+    }
+
+    pub fn run_shutdown(&mut self, services: &mut Services) {
+        self.shutdown.run(services);
+    }
+}
+
+/// Stage of systems running with fixed frequency
+struct Fixed {
+    hz: u32,
+    step: Duration,
+    accumulator: Duration,
+    stage: Stage,
+}
+
+impl Fixed {
+    fn new(hz: u32) -> Self {
+        Self {
+            hz,
+            step: Duration::from_secs(1) / hz,
+            accumulator: Duration::from_secs(0),
+            stage: Stage::new(Level::Fixed(hz)),
+        }
+    }
+}
+
+/// Stages of systems running on transitions of states of the same type
+struct Transitions {
+    driver: Driver,
+    stages: Vec<(Hook, Box<dyn Any + Send + Sync>, Stage)>,
+}
+
+impl Transitions {
+    fn new(driver: Driver) -> Self {
+        Self {
+            driver,
+            stages: Vec::new(),
+        }
+    }
+
+    fn stage(&mut self, hook: Hook, state: Box<dyn Any + Send + Sync>) -> &mut Stage {
+        let eq = self.driver.eq;
+        let index = match self.stages
+            .iter()
+            .position(|(h, s, _)| *h == hook && eq(s.as_ref(), state.as_ref()))
+        {
+            Some(index) => index,
+            None => {
+                let level = match hook {
+                    Hook::Enter => Level::Enter(self.driver.name),
+                    Hook::Exit => Level::Exit(self.driver.name),
+                };
+                self.stages.push((hook, state, Stage::new(level)));
+                self.stages.len() - 1
+            }
+        };
+        &mut self.stages[index].2
+    }
+
+    fn run(&mut self, hook: Hook, state: &dyn Any, services: &mut Services) {
+        let eq = self.driver.eq;
+        if let Some((_, _, stage)) = self.stages
+            .iter_mut()
+            .find(|(h, s, _)| *h == hook && eq(s.as_ref(), state))
+        {
+            stage.run(services);
+        }
+    }
+}
+
+/// System registered in a stage
+struct Node {
+    /// Sequence number of the registration
+    id: usize,
+    system: Box<dyn Systemized>,
+    order: Order,
+}
+
+/// Systems of the same run level grouped into batches. Systems of a batch have no conflicting
+/// access to services and run in parallel, while batches run one by one.
+struct Stage {
+    level: Level,
+    /// Systems sorted by batches
+    nodes: Vec<Node>,
+    /// Number of systems in each batch
+    batches: Vec<usize>,
+    /// Batches must be rebuilt before the next run
+    dirty: bool,
+}
+
+impl Stage {
+    fn new(level: Level) -> Self {
+        Self {
+            level,
+            nodes: Vec::new(),
+            batches: Vec::new(),
+            dirty: false,
         }
     }
+
+    fn add(&mut self, system: Box<dyn Systemized>, order: Order) {
+        let id = self.nodes.len();
+        self.nodes.push(Node { id, system, order });
+        self.dirty = true;
+    }
+
+    /// Sorts systems topologically using their ordering constraints and puts each system into the
+    /// batch following the last batch with a conflicting or preceding system. Systems without
+    /// constraints, but with conflicts, always run in the order they were added
+    fn build(&mut self) {
+        self.nodes.sort_by_key(|node| node.id);
+
+        let dependencies = self.dependencies();
+        let sorted = self.sort(&dependencies);
+        let count = self.nodes.len();
+        let mut batch_of = vec![0; count];
+        let mut position_of = vec![0; count];
+
+        for (position, &i) in sorted.iter().enumerate() {
+            let access = self.nodes[i].system.access();
+            batch_of[i] = sorted[0..position]
+                .iter()
+                .filter(|&&j| {
+                    dependencies[i].contains(&j)
+                        || conflicts(access, self.nodes[j].system.access())
+                })
+                .map(|&j| batch_of[j] + 1)
+                .max()
+                .unwrap_or(0);
+            position_of[i] = position;
+        }
+
+        let mut nodes = self.nodes.drain(..)
+            .enumerate()
+            .map(|(i, node)| ((batch_of[i], position_of[i]), node))
+            .collect::<Vec<_>>();
+        nodes.sort_by_key(|(key, _)| *key);
+
+        self.batches.clear();
+        for ((batch, _), node) in nodes.into_iter() {
+            if batch == self.batches.len() {
+                self.batches.push(0);
+            }
+            self.batches[batch] += 1;
+            self.nodes.push(node);
+        }
+        self.dirty = false;
+    }
+
+    /// Returns indices of systems, that must run before each system
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        let mut dependencies = vec![Vec::new(); self.nodes.len()];
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            for label in node.order.after.iter() {
+                for j in self.labeled(node, label) {
+                    dependencies[i].push(j);
+                }
+            }
+            for label in node.order.before.iter() {
+                for j in self.labeled(node, label) {
+                    dependencies[j].push(i);
+                }
+            }
+        }
+        dependencies
+    }
+
+    /// Returns indices of systems with the label, except the system referring to it
+    fn labeled(&self, node: &Node, label: &'static str) -> Vec<usize> {
+        let result = self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| other.id != node.id && other.order.labels.contains(&label))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        if result.is_empty() {
+            warn!("System `{}` refers to unknown label `{}`", node.system.name(), label);
+        }
+        result
+    }
+
+    /// Returns indices of systems sorted topologically, prefering the order of registration
+    fn sort(&self, dependencies: &[Vec<usize>]) -> Vec<usize> {
+        let count = dependencies.len();
+        let mut done = vec![false; count];
+        let mut sorted = Vec::with_capacity(count);
+
+        while sorted.len() < count {
+            let next = (0..count)
+                .find(|&i| !done[i] && dependencies[i].iter().all(|&j| done[j]));
+
+            if let Some(i) = next {
+                done[i] = true;
+                sorted.push(i);
+            } else {
+                let names = (0..count)
+                    .filter(|&i| !done[i])
+                    .map(|i| self.nodes[i].system.name())
+                    .collect::<Vec<_>>();
+                panic!("Systems ordering constraints have a cycle: {}", names.join(", "));
+            }
+        }
+        sorted
+    }
+
+    /// Runs batches one by one. Deferred changes made by systems of a batch are applied after the
+    /// batch is finished. Timings are recorded, if the `Profiler` service is enabled
+    fn run(&mut self, services: &mut Services) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        if self.dirty {
+            self.build();
+        }
+
+        let profiling = services.get::<Profiler>().map(|p| p.is_enabled()).unwrap_or(false);
+        let level = self.level;
+        let start = Instant::now();
+        let mut spans = Vec::new();
+
+        let mut nodes = self.nodes.as_mut_slice();
+        for &size in self.batches.iter() {
+            let (batch, rest) = nodes.split_at_mut(size);
+            if size == 1 {
+                spans.extend(batch[0].run(services, level, profiling));
+            } else {
+                let services = &*services;
+                spans.par_extend(
+                    batch.par_iter_mut().filter_map(|node| node.run(services, level, profiling))
+                );
+            }
+            for node in batch.iter_mut() {
+                node.system.apply(services);
+            }
+            nodes = rest;
+        }
+
+        if profiling {
+            if let Some(profiler) = services.get_mut::<Profiler>() {
+                let duration = start.elapsed();
+                profiler.record(LevelSpan { level, start, duration }, spans);
+            }
+        }
+    }
+}
+
+impl Node {
+    /// Runs the system, returns its timing if profiling
+    fn run(&mut self, services: &Services, level: Level, profiling: bool) -> Option<Span> {
+        if !profiling {
+            self.system.run(services);
+            return None;
+        }
+        let start = Instant::now();
+        self.system.run(services);
+        Some(Span {
+            name: self.system.name(),
+            level,
+            start,
+            duration: start.elapsed(),
+            thread: profiler::thread(),
+        })
+    }
+}
+
+/// Checks if two sets of access have conflicts
+fn conflicts(a: &[Access], b: &[Access]) -> bool {
+    a.iter().any(|a| b.iter().any(|b| a.conflicts(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::{
+        application::services::Services,
+        ecs::{After, Before, Commands, Const, Label, Mut, RunLevel, System},
+        frame::Frame,
+        world::World,
+    };
+    use super::Scheduler;
+
+    #[derive(Default)]
+    struct Log(Vec<&'static str>);
+    #[derive(Default)]
+    struct Counter(AtomicUsize);
+
+    fn first(mut log: Mut<Log>, counter: Const<Counter>) {
+        log.0.push("first");
+        counter.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn second(counter: Const<Counter>) {
+        counter.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn third(mut log: Mut<Log>, counter: Const<Counter>) {
+        log.0.push("third");
+        counter.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn parallel_batches() {
+        let mut services = Services::new();
+        services.add(Log::default());
+        services.add(Counter::default());
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(System::from(first));
+        scheduler.add(System::from(second));
+        scheduler.add(System::from(third));
+
+        for _ in 0..10 {
+            scheduler.run_standard(&mut services);
+        }
+
+        assert_eq!(scheduler.standard.batches, vec![2, 1]);
+        assert_eq!(services.get::<Counter>().unwrap().0.load(Ordering::SeqCst), 30);
+        let log = &services.get::<Log>().unwrap().0;
+        assert_eq!(log.len(), 20);
+        assert!(log.chunks(2).all(|pair| pair == ["first", "third"]));
+    }
+
+    fn fourth(mut log: Mut<Log>) {
+        log.0.push("fourth");
+    }
+
+    #[test]
+    fn ordering_constraints() {
+        let mut services = Services::new();
+        services.add(Log::default());
+        services.add(Counter::default());
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(System::from(fourth).with(Label("fourth")));
+        scheduler.add(System::from(third).with(After("fourth")));
+        scheduler.add(System::from(first).with(Label("first")).with(Before("fourth")));
+        scheduler.add(System::from(second).with(After("first")));
+
+        scheduler.run_standard(&mut services);
+
+        assert_eq!(services.get::<Log>().unwrap().0, vec!["first", "fourth", "third"]);
+        assert_eq!(scheduler.standard.batches, vec![1, 2, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle")]
+    fn ordering_cycle() {
+        let mut services = Services::new();
+        services.add(Log::default());
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(System::from(fourth).with(Label("a")).with(After("b")));
+        scheduler.add(System::from(fourth).with(Label("b")).with(After("a")));
+        scheduler.run_standard(&mut services);
+    }
+
+    fn fixed_step(frame: Const<Frame>, mut log: Mut<Log>) {
+        assert_eq!(frame.fixed_delta(), Duration::from_millis(100));
+        log.0.push("fixed");
+    }
+
+    #[test]
+    fn fixed_run_level() {
+        let mut services = Services::new();
+        services.add(Log::default());
+        services.add(Frame::new());
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(System::from(fixed_step).with(RunLevel::Fixed(10)));
+
+        scheduler.run_fixed(&mut services, Duration::from_millis(50));
+        assert_eq!(services.get::<Log>().unwrap().0.len(), 0);
+        assert!((services.get::<Frame>().unwrap().alpha() - 0.5).abs() < 0.001);
+
+        scheduler.run_fixed(&mut services, Duration::from_millis(200));
+        assert_eq!(services.get::<Log>().unwrap().0.len(), 2);
+        assert!((services.get::<Frame>().unwrap().alpha() - 0.5).abs() < 0.001);
+
+        scheduler.run_fixed(&mut services, Duration::from_secs(10));
+        assert_eq!(services.get::<Log>().unwrap().0.len(), 2 + super::MAX_FIXED_STEPS as usize);
+    }
+
+    struct Bullet;
+
+    fn shoot(mut commands: Commands) {
+        commands.spawn((Bullet,));
+    }
+
+    fn count_bullets(world: Const<World>, counter: Const<Counter>) {
+        counter.0.store(world.query::<(&Bullet,)>().count(), Ordering::SeqCst);
+    }
+
+    #[test]
+    fn commands_sync_point() {
+        let mut services = Services::new();
+        services.add(World::new());
+        services.add(Counter::default());
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(System::from(shoot).with(Label("shoot")));
+        scheduler.add(System::from(count_bullets).with(After("shoot")));
+        scheduler.run_standard(&mut services);
+
+        assert_eq!(scheduler.standard.batches, vec![1, 1]);
+        assert_eq!(services.get::<Counter>().unwrap().0.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,276 @@
+use std::any::{Any, TypeId};
+
+use crate::{
+    application::services::Services,
+    ecs::{Access, RunLevel, System, SystemOption, Systemized},
+};
+
+/// Trait of types describing application states, like menu, loading or playing
+pub trait StateLabel: Clone + PartialEq + Send + Sync + 'static {}
+impl<T: Clone + PartialEq + Send + Sync + 'static> StateLabel for T {}
+
+/// Service holding the current state of the application of the type `S`
+///
+/// Transitions requested by `set` are applied by the scheduler at the beginning of the next
+/// frame: `OnExit` systems of the current state run first, then `OnEnter` systems of the new one.
+/// `OnEnter` systems of the initial state run at the beginning of the first frame.
+pub struct State<S> {
+    current: S,
+    next: Option<S>,
+    entered: bool,
+}
+
+impl<S: StateLabel> State<S> {
+    /// Creates the service with the initial state
+    pub fn new(initial: S) -> Self {
+        Self {
+            current: initial,
+            next: None,
+            entered: false,
+        }
+    }
+
+    /// Returns the current state
+    pub fn get(&self) -> &S {
+        &self.current
+    }
+
+    /// Requests transition to the state. If several transitions were requested during a frame,
+    /// the last one wins
+    pub fn set(&mut self, next: S) {
+        self.next = Some(next);
+    }
+
+    /// Returns the requested state, if there is a pending transition
+    pub fn next(&self) -> Option<&S> {
+        self.next.as_ref()
+    }
+
+    /// Applies the requested transition, returns the previous and the new states
+    fn transit(&mut self) -> Option<(Option<S>, S)> {
+        if !self.entered {
+            self.entered = true;
+            self.next = None;
+            return Some((None, self.current.clone()));
+        }
+        let next = self.next.take().filter(|next| *next != self.current)?;
+        let previous = std::mem::replace(&mut self.current, next);
+        Some((Some(previous), self.current.clone()))
+    }
+}
+
+/// Option to run a system only in the state
+pub struct InState<S>(pub S);
+
+/// Option to run a system once, when the application enters the state
+pub struct OnEnter<S>(pub S);
+
+/// Option to run a system once, when the application exits the state
+pub struct OnExit<S>(pub S);
+
+impl<S: StateLabel> SystemOption<InState<S>> for System {
+    fn set_option(&mut self, option: InState<S>) {
+        let state = option.0;
+        self.add_condition(Condition {
+            access: Access::of::<State<S>>(false),
+            check: Box::new(move |services| {
+                services.get::<State<S>>().map(|s| s.current == state).unwrap_or(false)
+            }),
+        });
+    }
+}
+
+impl<S: StateLabel> SystemOption<OnEnter<S>> for System {
+    fn set_option(&mut self, option: OnEnter<S>) {
+        self.set_option(RunLevel::Transition(Transition::new(Hook::Enter, option.0)));
+    }
+}
+
+impl<S: StateLabel> SystemOption<OnExit<S>> for System {
+    fn set_option(&mut self, option: OnExit<S>) {
+        self.set_option(RunLevel::Transition(Transition::new(Hook::Exit, option.0)));
+    }
+}
+
+/// Condition of a system run
+pub struct Condition {
+    access: Access,
+    check: Box<dyn Fn(&Services) -> bool + Send + Sync>,
+}
+
+/// System running only if all its conditions are met
+pub(crate) struct Conditional {
+    system: Box<dyn Systemized>,
+    conditions: Vec<Condition>,
+    /// Access of the system and its conditions
+    access: Vec<Access>,
+}
+
+impl Conditional {
+    pub(crate) fn new(system: Box<dyn Systemized>, conditions: Vec<Condition>) -> Self {
+        let access = system.access()
+            .iter()
+            .copied()
+            .chain(conditions.iter().map(|c| c.access))
+            .collect();
+        Self {
+            system,
+            conditions,
+            access,
+        }
+    }
+}
+
+impl Systemized for Conditional {
+    fn name(&self) -> &'static str {
+        self.system.name()
+    }
+
+    fn run(&mut self, app: &Services) {
+        if self.conditions.iter().all(|condition| (condition.check)(app)) {
+            self.system.run(app);
+        }
+    }
+
+    fn access(&self) -> &[Access] {
+        &self.access
+    }
+
+    fn apply(&mut self, app: &mut Services) {
+        self.system.apply(app);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hook {
+    Enter,
+    Exit,
+}
+
+/// Type erased state of `OnEnter` and `OnExit` system options
+pub struct Transition {
+    pub(crate) hook: Hook,
+    pub(crate) state: Box<dyn Any + Send + Sync>,
+    pub(crate) driver: Driver,
+}
+
+impl Transition {
+    fn new<S: StateLabel>(hook: Hook, state: S) -> Self {
+        Self {
+            hook,
+            state: Box::new(state),
+            driver: Driver::of::<S>(),
+        }
+    }
+}
+
+/// Type erased previous and new states of a transition
+pub(crate) type Change = (Option<Box<dyn Any>>, Box<dyn Any>);
+
+/// Type erased functions to control `State<S>` service
+#[derive(Clone, Copy)]
+pub(crate) struct Driver {
+    pub(crate) type_id: TypeId,
+    /// Name of the state type
+    pub(crate) name: &'static str,
+    /// Applies the pending transition, returns the previous and the new states
+    pub(crate) transit: fn(&mut Services) -> Option<Change>,
+    /// Compares two states of the type
+    pub(crate) eq: fn(&dyn Any, &dyn Any) -> bool,
+}
+
+impl Driver {
+    fn of<S: StateLabel>() -> Self {
+        Self {
+            type_id: TypeId::of::<S>(),
+            name: std::any::type_name::<S>(),
+            transit: transit::<S>,
+            eq: eq::<S>,
+        }
+    }
+}
+
+fn transit<S: StateLabel>(services: &mut Services) -> Option<Change> {
+    let (previous, next) = services.get_mut::<State<S>>()?.transit()?;
+    Some((previous.map(|s| Box::new(s) as Box<dyn Any>), Box::new(next)))
+}
+
+fn eq<S: StateLabel>(a: &dyn Any, b: &dyn Any) -> bool {
+    match (a.downcast_ref::<S>(), b.downcast_ref::<S>()) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        application::services::Services,
+        ecs::{Mut, System},
+        scheduler::Scheduler,
+    };
+    use super::{InState, OnEnter, OnExit, State};
+
+    #[derive(Clone, PartialEq, Debug)]
+    enum Mode {
+        Menu,
+        Playing,
+    }
+
+    #[derive(Default)]
+    struct Log(Vec<&'static str>);
+
+    fn menu(mut log: Mut<Log>) {
+        log.0.push("menu");
+    }
+
+    fn play(mut log: Mut<Log>) {
+        log.0.push("play");
+    }
+
+    fn enter_menu(mut log: Mut<Log>) {
+        log.0.push("enter menu");
+    }
+
+    fn exit_menu(mut log: Mut<Log>) {
+        log.0.push("exit menu");
+    }
+
+    fn enter_playing(mut log: Mut<Log>) {
+        log.0.push("enter playing");
+    }
+
+    fn frame(scheduler: &mut Scheduler, services: &mut Services) {
+        scheduler.run_transitions(services);
+        scheduler.run_standard(services);
+    }
+
+    #[test]
+    fn transitions() {
+        let mut services = Services::new();
+        services.add(Log::default());
+        services.add(State::new(Mode::Menu));
+
+        let mut scheduler = Scheduler::new();
+        scheduler.add(System::from(menu).with(InState(Mode::Menu)));
+        scheduler.add(System::from(play).with(InState(Mode::Playing)));
+        scheduler.add(System::from(enter_menu).with(OnEnter(Mode::Menu)));
+        scheduler.add(System::from(exit_menu).with(OnExit(Mode::Menu)));
+        scheduler.add(System::from(enter_playing).with(OnEnter(Mode::Playing)));
+
+        frame(&mut scheduler, &mut services);
+        frame(&mut scheduler, &mut services);
+        services.get_mut::<State<Mode>>().unwrap().set(Mode::Playing);
+        frame(&mut scheduler, &mut services);
+        services.get_mut::<State<Mode>>().unwrap().set(Mode::Playing);
+        frame(&mut scheduler, &mut services);
+
+        assert_eq!(services.get::<State<Mode>>().unwrap().get(), &Mode::Playing);
+        assert_eq!(services.get::<Log>().unwrap().0, vec![
+            "enter menu", "menu",
+            "menu",
+            "exit menu", "enter playing", "play",
+            "play",
+        ]);
+    }
+}
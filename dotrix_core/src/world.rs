@@ -1,16 +1,30 @@
 mod container;
+mod hooks;
+mod index;
+mod scene;
+mod sparse;
 
 use std::{
     any::TypeId,
+    collections::HashMap,
     vec::Vec,
     marker::PhantomData
 };
 
-use container::Container;
+use container::{Container, Guarded};
+pub use container::Ref;
+use hooks::{Event, Hooks};
+use index::{signature, QueryCache, Signature};
+use scene::Registration;
+use sparse::{SparseIter, SparseIterMut, SparseSets};
+
+pub use index::{Filter, Rows};
+pub use sparse::StorageType;
+
+pub use scene::SceneError;
 
 use crate::{
-    count,
-    ecs::Component,
+    ecs::{CommandBuffer, Commands, Component, Entity},
     recursive,
 };
 
@@ -26,12 +40,35 @@ impl Context {
     }
 }
 
+/// Location of an entity slot in the World
+#[derive(Default)]
+struct Slot {
+    /// Generation of the slot, incremented on each despawn
+    generation: u32,
+    /// Index of the container and the row in it, if the entity is alive
+    location: Option<(usize, usize)>,
+}
+
 /// World implements a container for Systems, Entities and their Components and quering functionality
 pub struct World {
     /// Entities container grouped by archetypes
     content: Vec<Container>,
-    /// Spawn counter for Entity ID generation
+    /// Indices of containers by signatures of their archetypes
+    archetypes: HashMap<Signature, usize>,
+    /// Indices of containers matching queries
+    queries: QueryCache,
+    /// Components stored in sparse sets
+    sparse: SparseSets,
+    /// Component lifecycle hooks
+    hooks: Hooks,
+    /// Entities slots indexed by `Entity::index`
+    slots: Vec<Slot>,
+    /// Indices of the slots available for reuse
+    free_slots: Vec<u32>,
+    /// Spawn counter
     counter: u64,
+    /// Component types registered for serialization
+    registry: Vec<Registration>,
     name: String,
 }
 
@@ -44,43 +81,377 @@ impl World {
     pub fn new() -> Self {
         Self {
             content: Vec::new(),
+            archetypes: HashMap::new(),
+            queries: QueryCache::default(),
+            sparse: SparseSets::default(),
+            hooks: Hooks::default(),
+            slots: Vec::new(),
+            free_slots: Vec::new(),
             counter: 0,
+            registry: Vec::new(),
             name: String::from("MyWorld")
         }
     }
 
-    /// Spawn single or multiple entities in the world
-    pub fn spawn<T, I>(&mut self, iter: I) 
+    /// Spawn single or multiple entities in the world, returns handles of spawned entities
+    pub fn spawn<T, I>(&mut self, iter: I) -> Vec<Entity>
     where
         T: Archetype + Pattern,
         I: IntoIterator<Item = T>
     {
-        let container = if let Some(container) = self.content
-            .iter_mut()
-            .find(|s| T::matches(s) && s.len() == T::len())
-        {
-           container
-        } else {
-            let container = Container::new::<T>();
-            self.content.push(container);
-            self.content.last_mut().unwrap()
+        let sparse = &self.sparse;
+        let signature = T::signature()
+            .into_iter()
+            .filter(|key| !sparse.has(*key))
+            .collect::<Vec<_>>();
+        let index = match self.archetypes.get(&signature) {
+            Some(&index) => index,
+            None => {
+                let mut container = Container::new::<T>();
+                for key in T::signature().into_iter().filter(|key| sparse.has(*key)) {
+                    container.exclude(key);
+                }
+                self.add_container(signature, container)
+            }
         };
 
-        for entity in iter {
-            entity.store(container);
+        let mut result = Vec::new();
+
+        for components in iter {
+            let entity = self.next_entity();
+            let container = &mut self.content[index];
+            components.store(entity, container, &mut self.sparse);
+            let row = container.push_entity(entity);
+            self.slots[entity.index() as usize].location = Some((index, row));
             self.counter += 1;
+            result.push(entity);
+        }
+
+        if !self.hooks.is_empty() {
+            let keys = T::signature();
+            let mut buffer = CommandBuffer::default();
+            for &entity in result.iter() {
+                for &key in keys.iter() {
+                    self.trigger(Event::Add, key, entity, &mut buffer);
+                }
+            }
+            buffer.apply(self);
+        }
+
+        result
+    }
+
+    /// Despawn an entity with all its components, returns false if the entity does not exist
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        let (index, row) = match self.locate(entity) {
+            Some(location) => location,
+            None => return false,
+        };
+
+        let mut buffer = CommandBuffer::default();
+        if !self.hooks.is_empty() {
+            let mut keys = self.content[index].keys().collect::<Vec<_>>();
+            keys.extend(self.sparse.keys(entity));
+            for key in keys {
+                self.trigger(Event::Remove, key, entity, &mut buffer);
+            }
+        }
+
+        if let Some(moved) = self.content[index].swap_remove(row) {
+            self.slots[moved.index() as usize].location = Some((index, row));
+        }
+        self.sparse.despawn(entity);
+
+        self.slots[entity.index() as usize].location = None;
+        self.free_slot(entity);
+        buffer.apply(self);
+        true
+    }
+
+    /// Inserts a component to the entity moving it to the container of the new archetype. If the
+    /// entity already has a component of the same type, it will be replaced. Returns false if the
+    /// entity does not exist
+    ///
+    /// Replacement of a component triggers `on_remove` hooks for the old component before
+    /// `on_add` hooks for the new one
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> bool {
+        let (index, _) = match self.locate(entity) {
+            Some(location) => location,
+            None => return false,
+        };
+
+        let key = TypeId::of::<T>();
+        let mut buffer = CommandBuffer::default();
+        if self.has(&self.content[index], key, entity) {
+            self.trigger(Event::Remove, key, entity, &mut buffer);
+        }
+        self.put(entity, component);
+        self.trigger(Event::Add, key, entity, &mut buffer);
+        buffer.apply(self);
+        true
+    }
+
+    /// Removes a component from the entity moving it to the container of the new archetype.
+    /// Returns the component if the entity had it
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let (index, _) = self.locate(entity)?;
+        let key = TypeId::of::<T>();
+
+        if !self.has(&self.content[index], key, entity) {
+            return None;
+        }
+
+        let mut buffer = CommandBuffer::default();
+        self.trigger(Event::Remove, key, entity, &mut buffer);
+        let component = self.take::<T>(entity);
+        buffer.apply(self);
+        component
+    }
+
+    /// Adds a hook, that is called after a component of the type was added to an entity by
+    /// spawning or inserting
+    ///
+    /// Hooks can read the World and record changes of it, that are applied after the operation
+    pub fn on_add<T, F>(&mut self, hook: F)
+    where
+        T: Component,
+        F: Fn(&World, Entity, &mut Commands) + Send + Sync + 'static,
+    {
+        self.hooks.add(Event::Add, TypeId::of::<T>(), Box::new(hook));
+    }
+
+    /// Adds a hook, that is called before a component of the type is removed from an entity
+    /// by removing, replacing or despawning, so the component could be read by the hook.
+    /// Components of dropped worlds do not trigger hooks
+    pub fn on_remove<T, F>(&mut self, hook: F)
+    where
+        T: Component,
+        F: Fn(&World, Entity, &mut Commands) + Send + Sync + 'static,
+    {
+        self.hooks.add(Event::Remove, TypeId::of::<T>(), Box::new(hook));
+    }
+
+    /// Returns true if the entity exists in the world
+    pub fn exists(&self, entity: Entity) -> bool {
+        self.locate(entity).is_some()
+    }
+
+    /// Gets a component of the entity
+    ///
+    /// The component stays borrowed while the returned reference lives, so querying it mutably
+    /// at the same time panics
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let (index, row) = self.locate(entity)?;
+        if self.sparse.has(TypeId::of::<T>()) {
+            return self.sparse.get::<T>(entity);
+        }
+        self.content[index].get::<T>().map(|v| Ref::map(v, |v| &v[row]))
+    }
+
+    /// Gets a mutable component of the entity
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        let (index, row) = self.locate(entity)?;
+        if self.sparse.has(TypeId::of::<T>()) {
+            return self.sparse.get_mut::<T>(entity);
+        }
+        self.content[index].get_mut::<T>().map(|v| &mut v[row])
+    }
+
+    /// Sets the storage of the component type, components are stored in archetype tables by
+    /// default. Storage must be set before any component of the type is added to the world
+    pub fn register_storage<T: Component>(&mut self, storage: StorageType) {
+        let key = TypeId::of::<T>();
+        let exists = self.sparse.len(key) > 0 || self.content
+            .iter()
+            .any(|container| container.has(key) && !container.entities().is_empty());
+        if exists {
+            panic!(
+                "Storage of component `{}` must be set before adding the component",
+                std::any::type_name::<T>()
+            );
+        }
+
+        match storage {
+            StorageType::Table => self.sparse.unregister(key),
+            StorageType::SparseSet => {
+                self.sparse.register::<T>();
+                self.exclude_column(key);
+            },
+        };
+        self.queries.clear();
+    }
+
+    /// Removes the column of the component type from containers, that are all empty, and
+    /// reindexes them by their new signatures, so spawns never reach archetypes with the column
+    fn exclude_column(&mut self, key: TypeId) {
+        for index in 0..self.content.len() {
+            let container = &mut self.content[index];
+            if !container.has(key) {
+                continue;
+            }
+            let stale = signature(container.keys().collect());
+            container.exclude(key);
+            let signature = signature(container.keys().collect());
+            if self.archetypes.get(&stale) == Some(&index) {
+                self.archetypes.remove(&stale);
+            }
+            // a container of the same archetype without the column could exist already
+            self.archetypes.entry(signature).or_insert(index);
+        }
+    }
+
+    /// Calls hooks of the event for the component of the entity
+    fn trigger(&self, event: Event, key: TypeId, entity: Entity, buffer: &mut CommandBuffer) {
+        let hooks = self.hooks.get(event, key);
+        if hooks.is_empty() {
+            return;
+        }
+        let mut commands = buffer.commands();
+        for hook in hooks {
+            hook(self, entity, &mut commands);
+        }
+    }
+
+    /// Stores the component of the alive entity, replacing the existing one
+    fn put<T: Component>(&mut self, entity: Entity, component: T) {
+        let (index, row) = self.locate(entity).expect("Entity must be alive");
+
+        let component = match self.sparse.insert(entity, component) {
+            Ok(_) => return,
+            Err(component) => component,
+        };
+
+        if let Some(column) = self.content[index].get_mut::<T>() {
+            column[row] = component;
+            return;
+        }
+
+        let mut keys = self.content[index].keys().collect::<Vec<_>>();
+        keys.push(TypeId::of::<T>());
+        let target = self.find_or_create(index, keys, |container| container.init::<T>());
+
+        let (source, destination) = self.pair_mut(index, target);
+        let moved = source.move_row(row, destination);
+        destination.push(component);
+
+        self.relocate(entity, moved, (index, row), target);
+    }
+
+    /// Takes the component from the alive entity
+    fn take<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let (index, row) = self.locate(entity)?;
+        let key = TypeId::of::<T>();
+
+        if self.sparse.has(key) {
+            return self.sparse.remove::<T>(entity);
+        }
+
+        if !self.content[index].has(key) {
+            return None;
+        }
+
+        let keys = self.content[index].keys().filter(|k| *k != key).collect::<Vec<_>>();
+        let target = self.find_or_create(index, keys, |container| container.exclude(key));
+
+        let (source, destination) = self.pair_mut(index, target);
+        let component = source.take::<T>(row);
+        let moved = source.move_row(row, destination);
+
+        self.relocate(entity, moved, (index, row), target);
+        component
+    }
+
+    /// Returns a handle for a new entity, reusing free slots
+    fn next_entity(&mut self) -> Entity {
+        if let Some(index) = self.free_slots.pop() {
+            Entity::new(index, self.slots[index as usize].generation)
+        } else {
+            self.slots.push(Slot::default());
+            Entity::new((self.slots.len() - 1) as u32, 0)
+        }
+    }
+
+    /// Returns the slot of a handle, that was not spawned, for reuse
+    fn free_slot(&mut self, entity: Entity) {
+        let slot = &mut self.slots[entity.index() as usize];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(entity.index());
+    }
+
+    /// Finds a container with the set of components or creates it from the source container
+    fn find_or_create<F>(&mut self, source: usize, keys: Vec<TypeId>, init: F) -> usize
+    where
+        F: FnOnce(&mut Container),
+    {
+        let signature = signature(keys);
+        if let Some(&index) = self.archetypes.get(&signature) {
+            return index;
+        }
+        let mut container = self.content[source].empty();
+        init(&mut container);
+        self.add_container(signature, container)
+    }
+
+    /// Adds a container of the archetype, returns its index
+    fn add_container(&mut self, signature: Signature, container: Container) -> usize {
+        let index = self.content.len();
+        self.content.push(container);
+        self.archetypes.insert(signature, index);
+        index
+    }
+
+    /// Returns mutable references to two different containers
+    fn pair_mut(&mut self, a: usize, b: usize) -> (&mut Container, &mut Container) {
+        if a < b {
+            let (left, right) = self.content.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.content.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+
+    /// Updates slots after the entity was moved from the source row to the end of the target
+    /// container
+    fn relocate(
+        &mut self,
+        entity: Entity,
+        moved: Option<Entity>,
+        source: (usize, usize),
+        target: usize,
+    ) {
+        if let Some(moved) = moved {
+            self.slots[moved.index() as usize].location = Some(source);
         }
+        let row = self.content[target].entities().len() - 1;
+        self.slots[entity.index() as usize].location = Some((target, row));
+    }
+
+    /// Checks if the entity from the container has the component
+    fn has(&self, container: &Container, key: TypeId, entity: Entity) -> bool {
+        container.has(key) || self.sparse.contains(key, entity)
+    }
+
+    /// Returns container index and row of the entity if it is alive
+    fn locate(&self, entity: Entity) -> Option<(usize, usize)> {
+        self.slots
+            .get(entity.index() as usize)
+            .filter(|slot| slot.generation == entity.generation())
+            .and_then(|slot| slot.location)
     }
 
     /// Query stored components in the World
+    ///
+    /// Systems querying components mutably should access the World using `Mut` accessor, so the
+    /// scheduler won't run them in parallel with other systems querying the same components.
+    /// Containers matching the query are cached, until new archetypes appear in the world
     pub fn query<'w, Q>(&'w self) -> impl Iterator<Item = <<Q as Query>::Iter as Iterator>::Item> + 'w
     where
         Q: Query<'w>,
     {
-        let iter = self.content.iter()
-            .filter(|&container| Q::matches(container))
-            .map(|container| Q::select(&container))
-            .flatten();
+        let matches = self.queries.matches(Q::filters(), &self.content, &self.sparse);
+        let iter = (0..matches.len())
+            .flat_map(move |i| Q::select(&self.content[matches[i]], &self.sparse));
 
         Matches {
             iter,
@@ -149,22 +520,36 @@ where
 
 /// Trait definition of Entities with the same set of components
 pub trait Archetype {
-    fn store(self, container: &mut Container);
+    /// Stores components of the entity to the container or to sparse sets
+    fn store(self, entity: Entity, container: &mut Container, sparse: &mut SparseSets);
     fn map(container: &mut Container);
 }
 
 /// Trait definition of components set
 pub trait Pattern {
-    fn len() -> usize;
-    fn matches(container: &Container) -> bool;
+    /// Returns the signature of the archetype
+    fn signature() -> Signature;
+}
+
+/// Entities without components
+impl Archetype for () {
+    fn store(self, _: Entity, _: &mut Container, _: &mut SparseSets) {}
+    fn map(_: &mut Container) {}
+}
+
+impl Pattern for () {
+    fn signature() -> Signature {
+        Vec::new()
+    }
 }
 
 /// Trait definition of a Query
 pub trait Query<'w> {
     type Iter: Iterator + 'w;
 
-    fn select(container: &'w Container) -> Self::Iter;
-    fn matches(container: &'w Container) -> bool;
+    fn select(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter;
+    /// Returns filters, that containers must match
+    fn filters() -> Vec<Filter>;
 }
 
 /// Iterator or Query result
@@ -193,14 +578,64 @@ pub struct Zipper<'w, T> {
     _phantom: PhantomData<&'w ()>,
 }
 
+/// Iterator over rows of a container, yielding `Some(None)` for rows, that do not match
+pub trait Fetch {
+    type Item;
+
+    fn fetch(&mut self) -> Option<Option<Self::Item>>;
+}
+
+impl<I> Fetch for Guarded<'_, I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn fetch(&mut self) -> Option<Option<Self::Item>> {
+        self.next().map(Some)
+    }
+}
+
+impl<'w> Fetch for std::iter::Copied<std::slice::Iter<'w, Entity>> {
+    type Item = Entity;
+
+    #[inline]
+    fn fetch(&mut self) -> Option<Option<Self::Item>> {
+        self.next().map(Some)
+    }
+}
+
+/// Iterator of components stored in a column of the container or in a sparse set
+pub enum Components<T, S> {
+    Table(T),
+    Sparse(S),
+}
+
+impl<T, S, C> Fetch for Components<T, S>
+where
+    T: Iterator<Item = C>,
+    S: Iterator<Item = Option<C>>,
+{
+    type Item = C;
+
+    #[inline]
+    fn fetch(&mut self) -> Option<Option<Self::Item>> {
+        match self {
+            Components::Table(iter) => iter.next().map(Some),
+            Components::Sparse(iter) => iter.next(),
+        }
+    }
+}
+
 /// Trait defenition of Selector to control mutability of borrows
 pub trait Selector<'w> {
-    type Iter: Iterator;
+    type Iter: Fetch;
     type Component: Component;
 
-    fn borrow(container: &'w Container) -> Self::Iter;
-    fn matches(container: &'w Container) -> bool {
-        container.has(TypeId::of::<Self::Component>())
+    fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter;
+    fn filter() -> Filter {
+        Filter::With(TypeId::of::<Self::Component>())
     }
 }
 
@@ -208,11 +643,14 @@ impl<'w, C> Selector<'w> for &'_ C
 where
     C: Component,
 {
-    type Iter = std::slice::Iter<'w, C>;
+    type Iter = Components<Guarded<'w, std::slice::Iter<'w, C>>, SparseIter<'w, C>>;
     type Component = C;
 
-    fn borrow(container: &'w Container) -> Self::Iter {
-        container.get::<C>().unwrap().iter()
+    fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
+        match container.iter::<C>() {
+            Some(components) => Components::Table(components),
+            None => Components::Sparse(sparse.iter::<C>(container.entities()).unwrap()),
+        }
     }
 }
 
@@ -220,14 +658,167 @@ impl<'w, C> Selector<'w> for &'_ mut C
 where
     C: Component,
 {
-    type Iter = std::slice::IterMut<'w, C>;
+    type Iter = Components<Guarded<'w, std::slice::IterMut<'w, C>>, SparseIterMut<'w, C>>;
     type Component = C;
 
-    fn borrow(container: &'w Container) -> Self::Iter {
-        container.get_mut::<C>().unwrap().iter_mut()
+    fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
+        match container.iter_mut::<C>() {
+            Some(components) => Components::Table(components),
+            None => Components::Sparse(sparse.iter_mut::<C>(container.entities()).unwrap()),
+        }
+    }
+}
+
+impl<'w> Selector<'w> for Entity {
+    type Iter = std::iter::Copied<std::slice::Iter<'w, Entity>>;
+    type Component = Entity;
+
+    fn borrow(container: &'w Container, _: &'w SparseSets) -> Self::Iter {
+        container.entities().iter().copied()
+    }
+
+    fn filter() -> Filter {
+        Filter::Any
+    }
+}
+
+impl<'w, C> Selector<'w> for Option<&'_ C>
+where
+    C: Component,
+{
+    type Iter = Optional<<&'w C as Selector<'w>>::Iter>;
+    type Component = C;
+
+    fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
+        if let Some(components) = container.iter::<C>() {
+            return Optional::Some(Components::Table(components));
+        }
+        match sparse.iter::<C>(container.entities()) {
+            Some(components) => Optional::Some(Components::Sparse(components)),
+            None => Optional::None(container.entities().len()),
+        }
+    }
+
+    fn filter() -> Filter {
+        Filter::Any
+    }
+}
+
+impl<'w, C> Selector<'w> for Option<&'_ mut C>
+where
+    C: Component,
+{
+    type Iter = Optional<<&'w mut C as Selector<'w>>::Iter>;
+    type Component = C;
+
+    fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
+        if let Some(components) = container.iter_mut::<C>() {
+            return Optional::Some(Components::Table(components));
+        }
+        match sparse.iter_mut::<C>(container.entities()) {
+            Some(components) => Optional::Some(Components::Sparse(components)),
+            None => Optional::None(container.entities().len()),
+        }
+    }
+
+    fn filter() -> Filter {
+        Filter::Any
+    }
+}
+
+/// Iterator of optional components, yields `None` for each entity if the component is missing
+pub enum Optional<I> {
+    Some(I),
+    None(usize),
+}
+
+impl<I> Fetch for Optional<I>
+where
+    I: Fetch,
+{
+    type Item = Option<I::Item>;
+
+    #[inline]
+    fn fetch(&mut self) -> Option<Option<Self::Item>> {
+        match self {
+            Optional::Some(iter) => iter.fetch().map(Some),
+            Optional::None(count) => if *count > 0 {
+                *count -= 1;
+                Some(Some(None))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Query filter matching entities having the component without borrowing it
+pub struct With<C> {
+    _phantom: PhantomData<C>,
+}
+
+impl<'w, C> Selector<'w> for With<C>
+where
+    C: Component,
+{
+    type Iter = Rows;
+    type Component = C;
+
+    fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
+        Self::filter().rows(container, sparse)
+    }
+}
+
+/// Query filter matching entities not having the component
+pub struct Without<C> {
+    _phantom: PhantomData<C>,
+}
+
+impl<'w, C> Selector<'w> for Without<C>
+where
+    C: Component,
+{
+    type Iter = Rows;
+    type Component = C;
+
+    fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
+        Self::filter().rows(container, sparse)
+    }
+
+    fn filter() -> Filter {
+        Filter::Without(TypeId::of::<C>())
+    }
+}
+
+/// Query filter matching entities if any of selectors from the tuple matches, for example
+/// `Or<(With<A>, Without<B>)>`
+pub struct Or<T> {
+    _phantom: PhantomData<T>,
+}
+
+/// Macros implementing `Or` filter for different types of tuples
+macro_rules! impl_or {
+    ($($i: ident),*) => {
+        impl<'w, $($i),*> Selector<'w> for Or<($($i,)*)>
+        where
+            $($i: Selector<'w>,)*
+        {
+            type Iter = Rows;
+            type Component = ();
+
+            fn borrow(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
+                Self::filter().rows(container, sparse)
+            }
+
+            fn filter() -> Filter {
+                Filter::Or(vec![$($i::filter()),*])
+            }
+        }
     }
 }
 
+recursive!(impl_or, A, B, C, D);
+
 /// Macros implementing all necessary archetyoes, patterns, querries and iterators for different
 /// types of tuples
 #[macro_export]
@@ -240,10 +831,12 @@ macro_rules! impl_tuples {
             )*
         {
             #[allow(non_snake_case)]
-            fn store(self, container: &mut Container) {
+            fn store(self, entity: Entity, container: &mut Container, sparse: &mut SparseSets) {
                 let ($($i,)*) = self;
                 $(
-                    container.push::<$i>($i);
+                    if let Err($i) = sparse.insert(entity, $i) {
+                        container.push::<$i>($i);
+                    }
                 )*
             }
             fn map(container: &mut Container) {
@@ -257,12 +850,8 @@ macro_rules! impl_tuples {
         where
             $($i: Component,)*
         {
-            fn len() -> usize {
-                count!($($i,)*)
-            }
-
-            fn matches(container: &Container) -> bool {
-                $(container.has(TypeId::of::<$i>()))&&*
+            fn signature() -> Signature {
+                signature(vec![$(TypeId::of::<$i>()),*])
             }
         }
 
@@ -273,39 +862,42 @@ macro_rules! impl_tuples {
             type Iter = Zipper<'w, ($($i::Iter,)*)>;
             // type Iter = Zipper<'w, ($(std::slice::Iter<'w, $i>,)*)>;
 
-            fn select(container: &'w Container) -> Self::Iter {
+            fn select(container: &'w Container, sparse: &'w SparseSets) -> Self::Iter {
                 Zipper {
-                    tuple: ($({$i::borrow(container)},)*),
+                    tuple: ($({$i::borrow(container, sparse)},)*),
                     // tuple: ($(container.get::<$i::Component>().unwrap().into_iter(),)*),
                     _phantom: PhantomData,
                 }
             }
 
-            fn matches(container: &'w Container) -> bool
-            {
-                $(
-                    $i::matches(container)
-                )&&*
+            fn filters() -> Vec<Filter> {
+                // filters matching any container are skipped to share the cache between queries
+                vec![$($i::filter()),*]
+                    .into_iter()
+                    .filter(|filter| *filter != Filter::Any)
+                    .collect()
             }
         }
 
         #[allow(non_snake_case)]
         impl<'w, $($i),*> Iterator for Zipper<'w, ($($i,)*)>
         where
-            $($i: Iterator + 'w,)*
+            $($i: Fetch + 'w,)*
         {
             type Item = ($($i::Item,)*);
 
+            #[inline]
             fn next(&mut self) -> Option<Self::Item> {
                 let ($(ref mut $i,)*) = self.tuple;
-                $(
-                    let $i = match $i.next() {
-                        None => return None,
-                        Some(item) => item,
-                    };
-
-                )*
-                Some(($($i,)*))
+                // rows, that some of selectors do not match, are skipped
+                loop {
+                    $(
+                        let $i = $i.fetch()?;
+                    )*
+                    if let ($(Some($i),)*) = ($($i,)*) {
+                        return Some(($($i,)*));
+                    }
+                }
             }
         }
     }
@@ -315,7 +907,8 @@ recursive!(impl_tuples, A, B, C, D, E, F); //, E, F, G, H, I, J, K, L, M, N, O,
 
 #[cfg(test)]
 mod tests {
-    use super::World;
+    use super::{ Or, StorageType, With, Without, World };
+    use crate::ecs::Entity;
 
     struct Armor(u32);
     struct Health(u32);
@@ -378,4 +971,281 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn spawn_and_despawn() {
+        let mut world = World::new();
+        let entities = world.spawn((0..3).map(|i| (Armor(i), Health(100 + i))));
+        assert_eq!(entities.len(), 3);
+
+        assert_eq!(world.get::<Armor>(entities[1]).map(|a| a.0), Some(1));
+        world.get_mut::<Health>(entities[2]).unwrap().0 = 50;
+
+        assert!(world.despawn(entities[0]));
+        assert!(!world.despawn(entities[0]));
+        assert!(world.get::<Armor>(entities[0]).is_none());
+        // moved row must stay accessible by its handle
+        assert_eq!(world.get::<Health>(entities[2]).map(|h| h.0), Some(50));
+
+        // reused slot must not be accessible by a stale handle
+        let reused = world.spawn(Some((Armor(7), Health(7))))[0];
+        assert_eq!(reused.index(), entities[0].index());
+        assert!(world.get::<Armor>(entities[0]).is_none());
+        assert_eq!(world.get::<Armor>(reused).map(|a| a.0), Some(7));
+    }
+
+    #[test]
+    fn query_entities() {
+        let mut world = World::new();
+        let entities = world.spawn((0..3).map(|i| (Speed(i),)));
+        for (entity, speed) in world.query::<(Entity, &Speed)>() {
+            assert_eq!(entities[speed.0 as usize], entity);
+        }
+        assert_eq!(world.query::<(Entity,)>().count(), 3);
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut world = World::new();
+        let entities = world.spawn((0..3).map(|i| (Armor(i),)));
+
+        assert!(world.insert(entities[0], Health(10)));
+        assert!(world.insert(entities[1], Health(11)));
+        assert_eq!(world.query::<(&Armor, &Health)>().count(), 2);
+        assert_eq!(world.query::<(&Armor,)>().count(), 3);
+
+        // replace existing component
+        assert!(world.insert(entities[0], Health(20)));
+        assert_eq!(world.get::<Health>(entities[0]).map(|h| h.0), Some(20));
+        assert_eq!(world.get::<Health>(entities[1]).map(|h| h.0), Some(11));
+
+        assert_eq!(world.remove::<Armor>(entities[0]).map(|a| a.0), Some(0));
+        assert!(world.remove::<Armor>(entities[0]).is_none());
+        assert_eq!(world.get::<Health>(entities[0]).map(|h| h.0), Some(20));
+        assert_eq!(world.get::<Armor>(entities[2]).map(|a| a.0), Some(2));
+        assert_eq!(world.get::<Armor>(entities[1]).map(|a| a.0), Some(1));
+        assert_eq!(world.query::<(&Armor,)>().count(), 2);
+        assert_eq!(world.query::<(&Health,)>().count(), 2);
+
+        assert!(world.despawn(entities[1]));
+        assert!(!world.insert(entities[1], Speed(1)));
+        assert_eq!(world.get::<Health>(entities[0]).map(|h| h.0), Some(20));
+    }
+
+    #[test]
+    fn query_filters() {
+        let world = spawn();
+
+        let mut armors = world.query::<(&Armor, Option<&Health>)>()
+            .map(|(armor, health)| (armor.0, health.map(|h| h.0)))
+            .collect::<Vec<_>>();
+        armors.sort();
+        assert_eq!(armors, vec![(10, None), (100, Some(100))]);
+
+        for (speed, weight) in world.query::<(&Speed, Option<&mut Weight>)>() {
+            if let Some(weight) = weight {
+                weight.0 += speed.0;
+            }
+        }
+        assert_eq!(world.query::<(&Weight,)>().filter(|(w,)| w.0 == 5035).count(), 9);
+
+        assert_eq!(world.query::<(&Damage, With<Armor>)>().count(), 2);
+        assert_eq!(world.query::<(&Damage, Without<Armor>)>().count(), 1);
+        assert_eq!(world.query::<(&Speed, Without<Weight>, Without<Health>)>().count(), 1);
+        assert_eq!(world.query::<(Entity, Or<(With<Armor>, With<Weight>)>)>().count(), 11);
+        assert_eq!(world.query::<(&Speed, Or<(With<Health>, Without<Damage>)>)>().count(), 10);
+    }
+
+    #[test]
+    fn archetypes_index() {
+        let mut world = spawn();
+        let containers = world.content.len();
+        world.spawn(Some((Damage(1), Armor(1))));
+        world.spawn(Some((Weight(1), Speed(1))));
+        assert_eq!(world.content.len(), containers);
+
+        assert_eq!(world.query::<(&Speed,)>().count(), 12);
+        let entity = world.spawn(Some((Speed(1), Armor(1))))[0];
+        assert_eq!(world.content.len(), containers + 1);
+        // cached matches must be extended with the new archetype
+        assert_eq!(world.query::<(&Speed,)>().count(), 13);
+        assert_eq!(world.query::<(Entity, &Speed)>().count(), 13);
+
+        // archetypes created by insert and remove are indexed as well
+        world.insert(entity, Health(1));
+        world.remove::<Health>(entity);
+        assert_eq!(world.content.len(), containers + 2);
+        assert_eq!(world.query::<(&Speed, &Armor, Without<Health>)>().count(), 1);
+    }
+
+    struct Stunned(u32);
+
+    #[test]
+    fn sparse_storage() {
+        let mut world = World::new();
+        world.register_storage::<Stunned>(StorageType::SparseSet);
+        let entities = world.spawn((0..4).map(|i| (Speed(i), Stunned(i))));
+        world.spawn(Some((Armor(1),)));
+        let containers = world.content.len();
+
+        // toggling of a sparse component does not move the entity to another archetype
+        assert_eq!(world.remove::<Stunned>(entities[1]).map(|s| s.0), Some(1));
+        assert!(world.insert(entities[0], Stunned(10)));
+        assert!(world.insert(entities[3], Armor(3)));
+        assert!(world.despawn(entities[2]));
+        assert!(world.insert(entities[0], Stunned(20)));
+        assert_eq!(world.content.len(), containers + 1);
+        assert_eq!(world.get::<Stunned>(entities[0]).map(|s| s.0), Some(20));
+        assert!(world.get::<Stunned>(entities[1]).is_none());
+
+        for (speed, stunned) in world.query::<(&mut Speed, &Stunned)>() {
+            speed.0 += stunned.0;
+        }
+        let mut speeds = world.query::<(&Speed, Option<&Stunned>)>()
+            .map(|(speed, stunned)| (speed.0, stunned.map(|s| s.0)))
+            .collect::<Vec<_>>();
+        speeds.sort();
+        assert_eq!(speeds, vec![(1, None), (6, Some(3)), (20, Some(20))]);
+
+        assert_eq!(world.query::<(Entity, With<Stunned>)>().count(), 2);
+        assert_eq!(world.query::<(&Speed, Without<Stunned>)>().count(), 1);
+        assert_eq!(world.query::<(Entity, Or<(With<Stunned>, With<Armor>)>)>().count(), 3);
+        assert_eq!(world.query::<(&Armor, &mut Stunned)>().count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Stunned")]
+    fn sparse_storage_late_registration() {
+        let mut world = World::new();
+        world.spawn(Some((Stunned(1),)));
+        world.register_storage::<Stunned>(StorageType::SparseSet);
+    }
+
+    #[test]
+    fn sparse_storage_after_despawn() {
+        let mut world = World::new();
+        let entity = world.spawn(Some((Speed(1), Stunned(1))))[0];
+        world.spawn(Some((Speed(2),)));
+        world.despawn(entity);
+        world.register_storage::<Stunned>(StorageType::SparseSet);
+
+        let key = std::any::TypeId::of::<Stunned>();
+        assert!(world.content.iter().all(|container| !container.has(key)));
+
+        let containers = world.content.len();
+        let entity = world.spawn(Some((Speed(3), Stunned(3))))[0];
+        assert_eq!(world.content.len(), containers);
+        assert_eq!(world.get::<Stunned>(entity).map(|s| s.0), Some(3));
+        assert_eq!(world.query::<(&Speed, &Stunned)>().count(), 1);
+        assert_eq!(world.query::<(&Speed, Without<Stunned>)>().count(), 1);
+        assert!(world.remove::<Stunned>(entity).is_some());
+        assert_eq!(world.query::<(&Speed, Without<Stunned>)>().count(), 2);
+    }
+
+    struct Explosion(u32);
+
+    #[test]
+    fn lifecycle_hooks() {
+        use std::sync::{Arc, atomic::{AtomicU32, Ordering}};
+
+        let mut world = World::new();
+        world.register_storage::<Stunned>(StorageType::SparseSet);
+        let added = Arc::new(AtomicU32::new(0));
+        let counter = added.clone();
+        world.on_add::<Speed, _>(move |world, entity, _| {
+            counter.fetch_add(world.get::<Speed>(entity).unwrap().0, Ordering::SeqCst);
+        });
+        world.on_remove::<Health, _>(|world, entity, commands| {
+            let power = world.get::<Health>(entity).unwrap().0;
+            commands.spawn((Explosion(power),));
+        });
+        world.on_remove::<Stunned, _>(|_, entity, commands| {
+            commands.insert(entity, Speed(100));
+        });
+
+        let entities = world.spawn((1..4).map(|i| (Speed(i), Health(i * 10))));
+        assert_eq!(added.load(Ordering::SeqCst), 6);
+
+        world.insert(entities[0], Health(15));
+        world.remove::<Health>(entities[1]);
+        world.remove::<Health>(entities[1]);
+        world.despawn(entities[2]);
+        let mut explosions = world.query::<(&Explosion,)>().map(|(e,)| e.0).collect::<Vec<_>>();
+        explosions.sort();
+        assert_eq!(explosions, vec![10, 20, 30]);
+
+        world.insert(entities[0], Stunned(1));
+        world.despawn(entities[0]);
+        assert_eq!(added.load(Ordering::SeqCst), 6);
+        world.insert(entities[1], Stunned(1));
+        world.remove::<Stunned>(entities[1]);
+        assert_eq!(added.load(Ordering::SeqCst), 106);
+    }
+
+    #[test]
+    #[should_panic(expected = "Speed")]
+    fn query_aliasing() {
+        let world = spawn();
+        for _ in world.query::<(&mut Speed, &Speed)>() {}
+    }
+
+    #[test]
+    #[should_panic(expected = "Health")]
+    fn get_aliasing() {
+        let mut world = World::new();
+        let entity = world.spawn(Some((Health(100),)))[0];
+        let _health = world.get::<Health>(entity).unwrap();
+        for _ in world.query::<(&mut Health,)>() {}
+    }
+
+    #[test]
+    #[should_panic(expected = "Stunned")]
+    fn sparse_get_aliasing() {
+        let mut world = World::new();
+        world.register_storage::<Stunned>(StorageType::SparseSet);
+        let entity = world.spawn(Some((Stunned(1),)))[0];
+        let _stunned = world.get::<Stunned>(entity).unwrap();
+        for _ in world.query::<(&mut Stunned,)>() {}
+    }
+
+    #[test]
+    fn sparse_filter_order() {
+        let mut world = World::new();
+        world.register_storage::<Stunned>(StorageType::SparseSet);
+        world.spawn((0..3).map(|i| (Speed(i), Stunned(i))));
+        world.spawn(Some((Speed(3),)));
+
+        for (stunned, _) in world.query::<(&mut Stunned, With<Stunned>)>() {
+            stunned.0 += 10;
+        }
+        for (_, stunned) in world.query::<(With<Stunned>, &mut Stunned)>() {
+            stunned.0 += 10;
+        }
+        let mut stunned = world.query::<(&Stunned, Or<(With<Stunned>, Without<Speed>)>)>()
+            .map(|(stunned, _)| stunned.0)
+            .collect::<Vec<_>>();
+        stunned.sort_unstable();
+        assert_eq!(stunned, vec![20, 21, 22]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Stunned` is already borrowed")]
+    fn sparse_query_aliasing() {
+        let mut world = World::new();
+        world.register_storage::<Stunned>(StorageType::SparseSet);
+        world.spawn(Some((Stunned(1),)));
+        for _ in world.query::<(&mut Stunned, &Stunned)>() {}
+    }
+
+    #[test]
+    fn query_borrows_release() {
+        let world = spawn();
+        let first = world.query::<(&mut Speed,)>().count();
+        let second = world.query::<(&mut Speed,)>().count();
+        assert_eq!(first, second);
+
+        let entity = world.query::<(Entity, &Speed)>().next().unwrap().0;
+        assert!(world.get::<Speed>(entity).is_some());
+        assert_eq!(world.query::<(&mut Speed,)>().count(), first);
+    }
 }
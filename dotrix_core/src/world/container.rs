@@ -1,18 +1,220 @@
 use std::{
     any::{Any, TypeId},
+    cell::UnsafeCell,
     collections::HashMap,
+    fmt,
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use super::Archetype;
-use crate::ecs::Component;
+use crate::ecs::{Component, Entity};
+
+/// Type erased column of components of the same type
+pub trait Column: Any {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn swap_remove(&mut self, index: usize);
+    /// Creates new empty column of the same type
+    fn empty(&self) -> Box<dyn Column>;
+    /// Moves an item from the index to the end of the target column of the same type
+    fn move_item(&mut self, index: usize, target: &mut dyn Column);
+}
+
+impl<T: Component> Column for Vec<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn swap_remove(&mut self, index: usize) {
+        Vec::swap_remove(self, index);
+    }
+
+    fn empty(&self) -> Box<dyn Column> {
+        Box::new(Vec::<T>::new())
+    }
+
+    fn move_item(&mut self, index: usize, target: &mut dyn Column) {
+        let item = Vec::swap_remove(self, index);
+        target.as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Target column must be of the same type")
+            .push(item);
+    }
+}
+
+/// Value of the borrow counter of an exclusively borrowed storage
+const EXCLUSIVE: usize = usize::MAX;
+
+/// Column of components with a runtime borrow counter
+pub(super) struct Storage {
+    column: UnsafeCell<Box<dyn Column>>,
+    /// Number of shared borrows or `EXCLUSIVE` if the column is borrowed mutably
+    borrows: AtomicUsize,
+    /// Type name of the components for borrow conflict messages
+    name: &'static str,
+}
+
+impl Storage {
+    pub(super) fn new<T: Component>(column: Vec<T>) -> Self {
+        Self::from_column(Box::new(column), std::any::type_name::<T>())
+    }
+
+    fn from_column(column: Box<dyn Column>, name: &'static str) -> Self {
+        Self {
+            column: UnsafeCell::new(column),
+            borrows: AtomicUsize::new(0),
+            name,
+        }
+    }
+
+    /// Creates new storage with an empty column of the same type
+    fn empty(&mut self) -> Self {
+        let name = self.name;
+        Self::from_column(self.column().empty(), name)
+    }
+
+    pub(super) fn column(&mut self) -> &mut dyn Column {
+        self.column.get_mut().as_mut()
+    }
+
+    /// Returns the column for reading, holding a shared borrow
+    pub(super) fn read(&self) -> (&dyn Column, Borrow<'_>) {
+        let borrow = self.borrow();
+        (unsafe { &**self.column.get() }, borrow)
+    }
+
+    /// Returns the column for writing, holding an exclusive borrow
+    #[allow(clippy::mut_from_ref)]
+    pub(super) fn write(&self) -> (&mut dyn Column, Borrow<'_>) {
+        let borrow = self.borrow_mut();
+        (unsafe { &mut **self.column.get() }, borrow)
+    }
+
+    fn borrow(&self) -> Borrow<'_> {
+        let mut borrows = self.borrows.load(Ordering::Acquire);
+        loop {
+            if borrows == EXCLUSIVE {
+                panic!("Component `{}` is already borrowed mutably", self.name);
+            }
+            match self.borrows.compare_exchange_weak(
+                borrows, borrows + 1, Ordering::AcqRel, Ordering::Acquire
+            ) {
+                Ok(_) => break,
+                Err(current) => borrows = current,
+            }
+        }
+        Borrow { borrows: &self.borrows, exclusive: false }
+    }
+
+    fn borrow_mut(&self) -> Borrow<'_> {
+        if self.borrows
+            .compare_exchange(0, EXCLUSIVE, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            panic!("Component `{}` is already borrowed", self.name);
+        }
+        Borrow { borrows: &self.borrows, exclusive: true }
+    }
+}
+
+/// Guard releasing a borrow of the column on drop
+pub struct Borrow<'a> {
+    borrows: &'a AtomicUsize,
+    exclusive: bool,
+}
+
+impl Drop for Borrow<'_> {
+    fn drop(&mut self) {
+        if self.exclusive {
+            self.borrows.store(0, Ordering::Release);
+        } else {
+            self.borrows.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+/// Shared reference to a component, that keeps its column borrowed while it lives, so the
+/// component could not be queried mutably at the same time
+pub struct Ref<'a, T: ?Sized> {
+    value: &'a T,
+    _borrow: Borrow<'a>,
+}
+
+impl<'a, T: ?Sized> Ref<'a, T> {
+    pub(super) fn new(value: &'a T, borrow: Borrow<'a>) -> Self {
+        Self {
+            value,
+            _borrow: borrow,
+        }
+    }
+
+    /// Makes a reference to a part of the borrowed value, keeping the borrow
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> Ref<'a, U>
+    where
+        F: FnOnce(&T) -> &U,
+    {
+        Ref {
+            value: f(this.value),
+            _borrow: this._borrow,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Ref<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// Iterator holding a borrow of the column
+pub struct Guarded<'a, I> {
+    pub(super) iter: I,
+    pub(super) _borrow: Borrow<'a>,
+}
+
+impl<I> Iterator for Guarded<'_, I>
+where
+    I: Iterator,
+{
+    type Item = I::Item;
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
 
 pub struct Container {
-    components: HashMap<TypeId, Box<dyn Any>>,
+    entities: Vec<Entity>,
+    components: HashMap<TypeId, Storage>,
 }
 
+// Access to the columns is controlled by the borrow counters
+unsafe impl Send for Container {}
+unsafe impl Sync for Container {}
+
 impl Container {
     pub fn new<A: Archetype>() -> Self {
         let mut result = Self {
+            entities: Vec::new(),
             components: HashMap::new(),
         };
         A::map(&mut result);
@@ -21,7 +223,7 @@ impl Container {
 
     pub fn push<T: Component>(&mut self, component: T) {
         if let Some(v) = self.components.get_mut(&TypeId::of::<T>()) {
-            v.downcast_mut::<Vec<T>>().unwrap().push(component)
+            v.column().as_any_mut().downcast_mut::<Vec<T>>().unwrap().push(component)
         }
         /* TODO: remove this:
         self.components
@@ -33,31 +235,80 @@ impl Container {
             });*/
     }
 
+    /// Registers an entity for the row, which components were pushed last, returns the row
+    pub fn push_entity(&mut self, entity: Entity) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
+    }
+
     pub fn init<T: Component>(&mut self) {
-        self.components.insert(TypeId::of::<T>(), Box::new(Vec::<T>::new()));
+        self.components.insert(TypeId::of::<T>(), Storage::new(Vec::<T>::new()));
+    }
+
+    /// Creates new empty container with the same set of components
+    pub fn empty(&mut self) -> Self {
+        Self {
+            entities: Vec::new(),
+            components: self.components
+                .iter_mut()
+                .map(|(&key, storage)| (key, storage.empty()))
+                .collect(),
+        }
+    }
+
+    /// Removes a column of components from the container
+    pub fn exclude(&mut self, key: TypeId) {
+        self.components.remove(&key);
+    }
+
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut Vec<T>>
+    where T: Component
+    {
+        self.components
+            .get_mut(&TypeId::of::<T>())
+            .map(|v| v.column().as_any_mut().downcast_mut::<Vec<T>>().unwrap())
     }
 
-    pub fn get_mut<T: Component>(&self) -> Option<&mut Vec<T>>
+    /// Borrows a column of components for reading
+    pub fn get<T: Component>(&self) -> Option<Ref<'_, Vec<T>>>
     where T: Component
     {
         self.components
             .get(&TypeId::of::<T>())
             .map(|v| {
-                unsafe {
-                    let vec_ref = v.downcast_ref::<Vec<T>>().unwrap();
-                    let vec_ptr = vec_ref as *const Vec<T>;
-                    let mut_ptr = vec_ptr as *mut Vec<T>;
-                    &mut *mut_ptr
+                let (column, borrow) = v.read();
+                Ref::new(column.as_any().downcast_ref::<Vec<T>>().unwrap(), borrow)
+            })
+    }
+
+    /// Borrows a column of components for reading
+    pub fn iter<T: Component>(&self) -> Option<Guarded<'_, std::slice::Iter<'_, T>>>
+    where T: Component
+    {
+        self.components
+            .get(&TypeId::of::<T>())
+            .map(|v| {
+                let (column, borrow) = v.read();
+                Guarded {
+                    iter: column.as_any().downcast_ref::<Vec<T>>().unwrap().iter(),
+                    _borrow: borrow,
                 }
             })
     }
 
-    pub fn get<T: Component>(&self) -> Option<&Vec<T>>
+    /// Borrows a column of components for writing
+    pub fn iter_mut<T: Component>(&self) -> Option<Guarded<'_, std::slice::IterMut<'_, T>>>
     where T: Component
     {
         self.components
             .get(&TypeId::of::<T>())
-            .map(|v| v.downcast_ref::<Vec<T>>().unwrap())
+            .map(|v| {
+                let (column, borrow) = v.write();
+                Guarded {
+                    iter: column.as_any_mut().downcast_mut::<Vec<T>>().unwrap().iter_mut(),
+                    _borrow: borrow,
+                }
+            })
     }
 
     /*
@@ -78,6 +329,46 @@ impl Container {
     }
     */
 
+    /// Removes a row from all columns by swapping it with the last one. Returns the entity, that
+    /// was moved into the removed row, if any
+    pub fn swap_remove(&mut self, row: usize) -> Option<Entity> {
+        for storage in self.components.values_mut() {
+            storage.column().swap_remove(row);
+        }
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+
+    /// Takes a component from the row out of its column. Other columns must be aligned by
+    /// `move_row` call right after that
+    pub fn take<T: Component>(&mut self, row: usize) -> Option<T> {
+        self.components
+            .get_mut(&TypeId::of::<T>())
+            .map(|v| v.column().as_any_mut().downcast_mut::<Vec<T>>().unwrap().swap_remove(row))
+    }
+
+    /// Moves a row into the target container. Columns missing in the target container are
+    /// skipped, so their components must be taken out before. Returns the entity, that was moved
+    /// into the removed row, if any
+    pub fn move_row(&mut self, row: usize, target: &mut Container) -> Option<Entity> {
+        for (key, storage) in self.components.iter_mut() {
+            if let Some(target_storage) = target.components.get_mut(key) {
+                storage.column().move_item(row, target_storage.column());
+            }
+        }
+        target.entities.push(self.entities.swap_remove(row));
+        self.entities.get(row).copied()
+    }
+
+    /// Returns types of stored components
+    pub fn keys(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.components.keys().copied()
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
     pub fn has(&self, key: TypeId) -> bool {
         self.components.contains_key(&key)
     }
@@ -102,9 +393,19 @@ mod tests {
             i.0 += 198;
         }
 
-        for i in c.get::<Item1>().unwrap() {
+        for i in c.get::<Item1>().unwrap().iter() {
             assert_eq!(i.0, 321);
         }
 
     }
+
+    #[test]
+    #[should_panic(expected = "already borrowed")]
+    fn borrow_conflict() {
+        let mut c = Container::new::<(Item1,)>();
+        c.push::<Item1>(Item1(1));
+
+        let _read = c.iter::<Item1>();
+        let _write = c.iter_mut::<Item1>();
+    }
 }
@@ -0,0 +1,40 @@
+use std::{any::TypeId, collections::HashMap};
+
+use super::World;
+use crate::ecs::{Commands, Entity};
+
+/// Callback of a component lifecycle event
+///
+/// Hook gets read access to the World and records changes of it using `Commands`, that are
+/// applied right after the operation, that has triggered the hook
+pub type Hook = Box<dyn Fn(&World, Entity, &mut Commands) + Send + Sync>;
+
+/// Component lifecycle event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// Component was added to the entity
+    Add,
+    /// Component is going to be removed from the entity
+    Remove,
+}
+
+/// Hooks of component types
+#[derive(Default)]
+pub struct Hooks {
+    hooks: HashMap<(Event, TypeId), Vec<Hook>>,
+}
+
+impl Hooks {
+    pub fn add(&mut self, event: Event, key: TypeId, hook: Hook) {
+        self.hooks.entry((event, key)).or_default().push(hook);
+    }
+
+    /// Returns hooks of the event for the component type
+    pub fn get(&self, event: Event, key: TypeId) -> &[Hook] {
+        self.hooks.get(&(event, key)).map(|hooks| hooks.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use super::{Container, Fetch, SparseSets};
+
+/// Sorted set of component types identifying an archetype
+pub type Signature = Vec<TypeId>;
+
+/// Returns the signature of the set of component types
+pub fn signature(mut keys: Vec<TypeId>) -> Signature {
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// Static description of a query selector, used to match archetypes and as a key of cached
+/// query matches
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// Matches any archetype
+    Any,
+    /// Matches archetypes having the component
+    With(TypeId),
+    /// Matches archetypes not having the component
+    Without(TypeId),
+    /// Matches archetypes matching any of filters
+    Or(Vec<Filter>),
+}
+
+impl Filter {
+    /// Checks if the container could have matching entities. Containers never store sparse
+    /// components, so entities having them could be in any container
+    pub fn matches(&self, container: &Container, sparse: &SparseSets) -> bool {
+        match self {
+            Filter::Any => true,
+            Filter::With(key) => container.has(*key) || sparse.has(*key),
+            Filter::Without(key) => !container.has(*key),
+            Filter::Or(filters) => filters.iter().any(|f| f.matches(container, sparse)),
+        }
+    }
+
+    /// Returns matching rows of the container
+    pub fn rows(&self, container: &Container, sparse: &SparseSets) -> Rows {
+        match self.mask(container, sparse) {
+            Some(mask) => Rows::Mask(mask.into_iter()),
+            None => Rows::All(container.entities().len()),
+        }
+    }
+
+    /// Returns flags of matching rows or `None` if all rows match
+    fn mask(&self, container: &Container, sparse: &SparseSets) -> Option<Vec<bool>> {
+        let entities = container.entities();
+        match self {
+            Filter::Any => None,
+            Filter::With(key) if container.has(*key) => None,
+            Filter::With(key) => Some(entities.iter().map(|e| sparse.contains(*key, *e)).collect()),
+            Filter::Without(key) if sparse.has(*key) => {
+                Some(entities.iter().map(|e| !sparse.contains(*key, *e)).collect())
+            },
+            Filter::Without(key) if container.has(*key) => Some(vec![false; entities.len()]),
+            Filter::Without(_) => None,
+            Filter::Or(filters) => {
+                let mut result = vec![false; entities.len()];
+                for filter in filters.iter() {
+                    let mask = filter.mask(container, sparse)?;
+                    for (result, row) in result.iter_mut().zip(mask) {
+                        *result |= row;
+                    }
+                }
+                Some(result)
+            },
+        }
+    }
+}
+
+/// Iterator of rows matching a filter
+pub enum Rows {
+    /// Number of rows, that all match
+    All(usize),
+    /// Flags of matching rows
+    Mask(std::vec::IntoIter<bool>),
+}
+
+impl Fetch for Rows {
+    type Item = ();
+
+    #[inline]
+    fn fetch(&mut self) -> Option<Option<Self::Item>> {
+        match self {
+            Rows::All(count) => if *count > 0 {
+                *count -= 1;
+                Some(Some(()))
+            } else {
+                None
+            },
+            Rows::Mask(mask) => mask.next().map(|row| if row { Some(()) } else { None }),
+        }
+    }
+}
+
+/// Indices of containers matching a query
+struct Matches {
+    /// Number of containers, that were checked
+    checked: usize,
+    indices: Arc<[usize]>,
+}
+
+/// Cache of containers matching queries
+///
+/// Containers are never removed from the world, so lists of matches are only extended with
+/// containers created after the previous query of the same kind.
+#[derive(Default)]
+pub struct QueryCache {
+    queries: RwLock<HashMap<Vec<Filter>, Matches>>,
+}
+
+impl QueryCache {
+    /// Returns indices of containers matching all the filters
+    pub fn matches(
+        &self,
+        filters: Vec<Filter>,
+        content: &[Container],
+        sparse: &SparseSets,
+    ) -> Arc<[usize]> {
+        {
+            let queries = self.queries.read().expect("Query cache lock is poisoned");
+            if let Some(matches) = queries.get(&filters).filter(|m| m.checked == content.len()) {
+                return matches.indices.clone();
+            }
+        }
+
+        let mut queries = self.queries.write().expect("Query cache lock is poisoned");
+        let (checked, mut indices) = match queries.get(&filters) {
+            // could be updated by another thread, while the lock was released
+            Some(matches) if matches.checked == content.len() => return matches.indices.clone(),
+            Some(matches) => (matches.checked, matches.indices.to_vec()),
+            None => (0, Vec::new()),
+        };
+        indices.extend(
+            (checked..content.len())
+                .filter(|&index| filters.iter().all(|f| f.matches(&content[index], sparse)))
+        );
+        let indices: Arc<[usize]> = Arc::from(indices);
+        queries.insert(filters, Matches { checked: content.len(), indices: indices.clone() });
+        indices
+    }
+
+    /// Drops all cached matches
+    pub fn clear(&mut self) {
+        self.queries.get_mut().expect("Query cache lock is poisoned").clear();
+    }
+}
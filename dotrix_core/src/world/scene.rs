@@ -0,0 +1,491 @@
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    io::{Read, Write},
+    marker::PhantomData,
+};
+
+use serde::{
+    de::{self, DeserializeOwned, Deserializer, Visitor},
+    ser::{self, Serializer},
+    Deserialize,
+    Serialize,
+};
+use serde_json::{Map, Value};
+
+use super::{Container, SparseSets, World, hooks::Event, index::signature};
+use crate::{
+    assets::{Assets, Id, RawId},
+    ecs::{CommandBuffer, Component, Entity},
+};
+
+/// Serialization functions of a component type registered by `World::register`
+pub(super) struct Registration {
+    name: &'static str,
+    type_id: TypeId,
+    save: fn(&World, Entity) -> Option<serde_json::Result<Value>>,
+    load: fn(Value) -> serde_json::Result<Box<dyn Any>>,
+    /// Adds a column of the component type to the container
+    init: fn(&mut Container),
+    /// Stores the loaded component of the entity to the container or to sparse sets
+    store: fn(Box<dyn Any>, Entity, &mut Container, &mut SparseSets),
+}
+
+impl Registration {
+    fn of<T>(name: &'static str) -> Self
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        Self {
+            name,
+            type_id: TypeId::of::<T>(),
+            save: save_component::<T>,
+            load: load_component::<T>,
+            init: Container::init::<T>,
+            store: store_component::<T>,
+        }
+    }
+}
+
+fn save_component<T>(world: &World, entity: Entity) -> Option<serde_json::Result<Value>>
+where
+    T: Component + Serialize,
+{
+    world.get::<T>(entity).map(|component| serde_json::to_value(&*component))
+}
+
+fn load_component<T>(value: Value) -> serde_json::Result<Box<dyn Any>>
+where
+    T: Component + DeserializeOwned,
+{
+    let component: T = serde_json::from_value(value)?;
+    Ok(Box::new(component))
+}
+
+fn store_component<T>(
+    component: Box<dyn Any>,
+    entity: Entity,
+    container: &mut Container,
+    sparse: &mut SparseSets,
+) where
+    T: Component,
+{
+    let component = *component.downcast::<T>().expect("Component must be of the registered type");
+    if let Err(component) = sparse.insert(entity, component) {
+        container.push(component);
+    }
+}
+
+/// Loaded components of an entity with indices of their registrations
+type Loaded = Vec<(usize, Box<dyn Any>)>;
+
+/// Scene file format
+#[derive(Serialize, Deserialize)]
+struct Scene {
+    /// Serializable components of each entity by their registered names
+    entities: Vec<Map<String, Value>>,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Json(serde_json::Error),
+    UnknownComponent(String),
+}
+
+impl std::error::Error for SceneError {}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneError::Json(err) =>
+                write!(f, "Can't process scene JSON ({:?})", err),
+            SceneError::UnknownComponent(name) =>
+                write!(f, "Component {:?} is not registered for serialization", name),
+        }
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        SceneError::Json(err)
+    }
+}
+
+impl World {
+    /// Registers a component type for serialization under the name used in scene files
+    pub fn register<T>(&mut self, name: &'static str)
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        self.registry.retain(|r| r.name != name && r.type_id != TypeId::of::<T>());
+        self.registry.push(Registration::of::<T>(name));
+    }
+
+    /// Saves entities having registered components to the JSON scene. Components of other types
+    /// are skipped. Asset ids are written as asset names from the registry of `Assets`, saving
+    /// fails if an asset has no name.
+    pub fn save(&self, writer: impl Write, assets: &Assets) -> Result<(), SceneError> {
+        let mut saved = Vec::new();
+        let mut indices = HashMap::new();
+
+        for container in self.content.iter() {
+            for entity in container.entities() {
+                let registered = self.registry
+                    .iter()
+                    .filter(|r| self.has(container, r.type_id, *entity))
+                    .collect::<Vec<_>>();
+
+                if !registered.is_empty() {
+                    indices.insert(*entity, indices.len());
+                    saved.push((*entity, registered));
+                }
+            }
+        }
+
+        let scope = Scope::Save { assets, entities: indices };
+        let entities = scope.enter(|| {
+            let mut entities = Vec::new();
+            for (entity, registered) in saved.iter() {
+                let mut components = Map::new();
+                for registration in registered.iter() {
+                    if let Some(value) = (registration.save)(self, *entity) {
+                        components.insert(registration.name.to_string(), value?);
+                    }
+                }
+                entities.push(components);
+            }
+            Ok::<_, serde_json::Error>(entities)
+        })?;
+
+        serde_json::to_writer_pretty(writer, &Scene { entities })?;
+        Ok(())
+    }
+
+    /// Spawns entities from the JSON scene, returns their handles. Asset names are resolved
+    /// through the registry of `Assets`, so assets could be imported before or after loading.
+    /// If the scene can't be loaded, none of its entities is spawned.
+    ///
+    /// Each entity is stored directly to the container of its final archetype, `on_add` hooks
+    /// are called after all entities of the scene were spawned.
+    pub fn load(&mut self, reader: impl Read, assets: &mut Assets) -> Result<Vec<Entity>, SceneError> {
+        let scene: Scene = serde_json::from_reader(reader)?;
+        // handles are reserved before deserialization, so components could refer to entities
+        let entities = scene.entities.iter().map(|_| self.next_entity()).collect::<Vec<_>>();
+
+        let scope = Scope::Load { assets, entities: entities.clone() };
+        let result = scope.enter(|| {
+            let mut loaded = Vec::with_capacity(scene.entities.len());
+            for components in scene.entities {
+                let mut entity = Loaded::with_capacity(components.len());
+                for (name, value) in components {
+                    let index = self.registry
+                        .iter()
+                        .position(|r| r.name == name)
+                        .ok_or(SceneError::UnknownComponent(name))?;
+                    entity.push((index, (self.registry[index].load)(value)?));
+                }
+                loaded.push(entity);
+            }
+            Ok::<_, SceneError>(loaded)
+        });
+
+        let loaded = match result {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                for entity in entities {
+                    self.free_slot(entity);
+                }
+                return Err(err);
+            }
+        };
+
+        let mut keys = Vec::with_capacity(entities.len());
+        for (&entity, components) in entities.iter().zip(loaded) {
+            keys.push(self.spawn_loaded(entity, components));
+        }
+
+        if !self.hooks.is_empty() {
+            let mut buffer = CommandBuffer::default();
+            for (&entity, keys) in entities.iter().zip(keys) {
+                for key in keys {
+                    self.trigger(Event::Add, key, entity, &mut buffer);
+                }
+            }
+            buffer.apply(self);
+        }
+        Ok(entities)
+    }
+
+    /// Stores components of the reserved entity to the container of their archetype, returns
+    /// types of the components
+    fn spawn_loaded(&mut self, entity: Entity, components: Loaded) -> Vec<TypeId> {
+        let keys = components
+            .iter()
+            .map(|(index, _)| self.registry[*index].type_id)
+            .collect::<Vec<_>>();
+        let sparse = &self.sparse;
+        let signature = signature(keys.iter().copied().filter(|key| !sparse.has(*key)).collect());
+
+        let index = match self.archetypes.get(&signature) {
+            Some(&index) => index,
+            None => {
+                let mut container = Container::new::<()>();
+                for (index, _) in components.iter() {
+                    let registration = &self.registry[*index];
+                    if !self.sparse.has(registration.type_id) {
+                        (registration.init)(&mut container);
+                    }
+                }
+                self.add_container(signature, container)
+            }
+        };
+
+        let container = &mut self.content[index];
+        for (registration, component) in components {
+            (self.registry[registration].store)(component, entity, container, &mut self.sparse);
+        }
+        let row = container.push_entity(entity);
+        self.slots[entity.index() as usize].location = Some((index, row));
+        self.counter += 1;
+        keys
+    }
+}
+
+/// Context of the scene being saved or loaded by the current thread
+enum Scope {
+    Save {
+        assets: *const Assets,
+        entities: HashMap<Entity, usize>,
+    },
+    Load {
+        assets: *mut Assets,
+        entities: Vec<Entity>,
+    },
+}
+
+thread_local! {
+    static SCOPE: RefCell<Option<Scope>> = const { RefCell::new(None) };
+}
+
+/// Guard leaving the scope on drop, even if serialization panics
+struct ScopeGuard;
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        SCOPE.with(|scope| scope.borrow_mut().take());
+    }
+}
+
+impl Scope {
+    fn enter<F, R>(self, func: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        SCOPE.with(|scope| *scope.borrow_mut() = Some(self));
+        let _guard = ScopeGuard;
+        func()
+    }
+}
+
+/// Returns registered name of the asset, if a scene is being saved
+fn asset_name(id: RawId) -> Option<Option<String>> {
+    SCOPE.with(|scope| match scope.borrow().as_ref() {
+        Some(Scope::Save { assets, .. }) => Some(unsafe { &**assets }.name(id).map(String::from)),
+        _ => None,
+    })
+}
+
+/// Returns id of the asset by its name, if a scene is being loaded
+fn asset_id(name: &str) -> Option<RawId> {
+    SCOPE.with(|scope| match scope.borrow().as_ref() {
+        Some(Scope::Load { assets, .. }) => Some(unsafe { &mut **assets }.register_name(name)),
+        _ => None,
+    })
+}
+
+/// Asset ids are serialized as asset names in scenes, so saving a scene fails if an asset has no
+/// name. Outside of scenes raw ids are written, that are valid only in the running process
+impl<T> Serialize for Id<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.is_null() {
+            return serializer.serialize_none();
+        }
+        match asset_name(self.id) {
+            Some(Some(name)) => serializer.serialize_str(&name),
+            Some(None) => Err(ser::Error::custom(format!("Asset id {} has no name", self.id))),
+            None => serializer.serialize_u64(self.id),
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Id<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(IdVisitor(PhantomData))
+    }
+}
+
+/// Visitor accepting asset names, raw ids and nulls
+struct IdVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for IdVisitor<T> {
+    type Value = Id<T>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "an asset name, id or null")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Id::default())
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Id::default())
+    }
+
+    fn visit_u64<E: de::Error>(self, id: u64) -> Result<Self::Value, E> {
+        Ok(Id::new(id))
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+        asset_id(name)
+            .map(Id::new)
+            .ok_or_else(|| E::custom("Asset names can be resolved only while loading a scene"))
+    }
+}
+
+/// Entities are serialized as indices of entities in the scene
+impl Serialize for Entity {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let index = SCOPE.with(|scope| match scope.borrow().as_ref() {
+            Some(Scope::Save { entities, .. }) => entities.get(self).copied(),
+            _ => None,
+        });
+        match index {
+            Some(index) => serializer.serialize_u64(index as u64),
+            None => Err(ser::Error::custom(format!("{:?} is not a part of the saved scene", self))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Entity {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let index = u64::deserialize(deserializer)? as usize;
+        SCOPE.with(|scope| match scope.borrow().as_ref() {
+            Some(Scope::Load { entities, .. }) => entities.get(index).copied(),
+            _ => None,
+        }).ok_or_else(|| de::Error::custom(format!("Entity #{} is not a part of the scene", index)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        assets::{Assets, Id, Resource},
+        ecs::Entity,
+        world::World,
+    };
+
+    #[derive(Serialize, Deserialize)]
+    struct Health(u32);
+
+    #[derive(Serialize, Deserialize)]
+    struct Follow(Entity);
+
+    #[derive(Serialize, Deserialize)]
+    struct Look(Id<Resource>);
+
+    struct Cache(u32);
+
+    fn new_world() -> World {
+        let mut world = World::new();
+        world.register::<Health>("Health");
+        world.register::<Follow>("Follow");
+        world.register::<Look>("Look");
+        world
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut assets = Assets::new();
+        let look = assets.register::<Resource>("fox");
+
+        let mut world = new_world();
+        let leader = world.spawn(Some((Health(100), Look(look), Cache(1))))[0];
+        world.spawn(Some((Health(50), Follow(leader))));
+        world.spawn(Some((Cache(2),)));
+
+        let mut scene = Vec::new();
+        world.save(&mut scene, &assets).unwrap();
+        assert!(String::from_utf8_lossy(&scene).contains("\"fox\""));
+
+        let mut assets = Assets::new();
+        assets.register::<Resource>("something else");
+        let mut world = new_world();
+        let entities = world.load(scene.as_slice(), &mut assets).unwrap();
+        assert_eq!(entities.len(), 2);
+
+        let look = world.get::<Look>(entities[0]).unwrap().0;
+        assert_eq!(Some(look), assets.find::<Resource>("fox"));
+        assert!(world.get::<Cache>(entities[0]).is_none());
+
+        let follow = world.get::<Follow>(entities[1]).unwrap();
+        assert_eq!(follow.0, entities[0]);
+        assert_eq!(world.get::<Health>(follow.0).unwrap().0, 100);
+    }
+
+    #[test]
+    fn unnamed_asset() {
+        let assets = Assets::new();
+        let mut world = new_world();
+        world.spawn(Some((Look(Id::new(42)),)));
+
+        let error = world.save(Vec::new(), &assets).unwrap_err();
+        assert!(error.to_string().contains("Asset id 42 has no name"));
+    }
+
+    #[test]
+    fn load_to_final_archetypes() {
+        let mut world = new_world();
+        let scene = r#"{ "entities": [
+            { "Health": 1, "Follow": 1 },
+            { "Follow": 0, "Health": 2 },
+            { "Health": 3 }
+        ] }"#;
+        let entities = world.load(scene.as_bytes(), &mut Assets::new()).unwrap();
+
+        // no intermediate archetypes are created while components are added
+        assert_eq!(world.content.len(), 2);
+        assert_eq!(world.query::<(&Health, &Follow)>().count(), 2);
+        assert_eq!(world.get::<Follow>(entities[0]).unwrap().0, entities[1]);
+        assert_eq!(world.get::<Health>(entities[2]).unwrap().0, 3);
+    }
+
+    #[test]
+    fn unknown_component() {
+        let mut world = new_world();
+        let scene = r#"{ "entities": [ { "Health": 1 }, { "Mana": 2 } ] }"#;
+        let result = world.load(scene.as_bytes(), &mut Assets::new());
+        assert!(result.is_err());
+        assert_eq!(world.query::<(Entity,)>().count(), 0);
+
+        // handles reserved for the failed scene are reused
+        let entity = world.spawn(Some((Health(1),)))[0];
+        assert!(world.exists(entity));
+        assert_eq!(world.query::<(Entity,)>().count(), 1);
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    any::TypeId,
+    collections::HashMap,
+};
+
+use super::container::{Borrow, Ref, Storage};
+use crate::ecs::{Component, Entity};
+
+/// Storage of a component type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageType {
+    /// Components are stored in columns of archetype tables, what makes queries faster, but
+    /// adding or removing a component moves all components of the entity to another table
+    Table,
+    /// Components are stored in a sparse set, so adding or removing them does not move other
+    /// components of the entity. Good for markers and statuses, that are toggled frequently
+    SparseSet,
+}
+
+/// Value of the sparse index of entities without a component
+const EMPTY: usize = usize::MAX;
+
+/// Components of the same type mapped to entities
+///
+/// Only the packed components are guarded by the borrow counter. Owners and the sparse index
+/// change only with `&mut` access to the world, so filters could check, if an entity has the
+/// component, while the components are borrowed by the same query.
+pub struct SparseSet {
+    /// Owners of the packed components
+    entities: Vec<Entity>,
+    /// Positions of components in the dense column indexed by `Entity::index`
+    sparse: Vec<usize>,
+    /// Components packed without gaps in the order of their owners
+    dense: Storage,
+}
+
+impl SparseSet {
+    fn new<T: Component>() -> Self {
+        Self {
+            entities: Vec::new(),
+            sparse: Vec::new(),
+            dense: Storage::new(Vec::<T>::new()),
+        }
+    }
+
+    fn position(&self, entity: Entity) -> Option<usize> {
+        self.sparse
+            .get(entity.index() as usize)
+            .copied()
+            .filter(|&position| position != EMPTY && self.entities[position] == entity)
+    }
+
+    fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    fn contains(&self, entity: Entity) -> bool {
+        self.position(entity).is_some()
+    }
+
+    fn dense_mut<T: Component>(&mut self) -> &mut Vec<T> {
+        self.dense
+            .column()
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Sparse set must be of the component type")
+    }
+
+    /// Borrows the component of the entity for reading
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let position = self.position(entity)?;
+        let (dense, borrow) = self.dense.read();
+        let dense = dense
+            .as_any()
+            .downcast_ref::<Vec<T>>()
+            .expect("Sparse set must be of the component type");
+        Some(Ref::new(&dense[position], borrow))
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        let position = self.position(entity)?;
+        Some(&mut self.dense_mut::<T>()[position])
+    }
+
+    /// Inserts the component, returns the replaced one
+    fn insert<T: Component>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(position) = self.position(entity) {
+            return Some(std::mem::replace(&mut self.dense_mut::<T>()[position], component));
+        }
+        let index = entity.index() as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, EMPTY);
+        }
+        self.sparse[index] = self.entities.len();
+        self.dense_mut::<T>().push(component);
+        self.entities.push(entity);
+        None
+    }
+
+    fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let position = self.unlink(entity)?;
+        Some(self.dense_mut::<T>().swap_remove(position))
+    }
+
+    /// Drops the component of the entity
+    fn discard(&mut self, entity: Entity) -> bool {
+        match self.unlink(entity) {
+            Some(position) => {
+                self.dense.column().swap_remove(position);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Removes the entity from the index, returns the position of its component, that must be
+    /// swap removed from the dense column
+    fn unlink(&mut self, entity: Entity) -> Option<usize> {
+        let position = self.position(entity)?;
+        self.sparse[entity.index() as usize] = EMPTY;
+        self.entities.swap_remove(position);
+        if let Some(moved) = self.entities.get(position) {
+            self.sparse[moved.index() as usize] = position;
+        }
+        Some(position)
+    }
+}
+
+/// Sparse sets of all component types registered with `StorageType::SparseSet`
+#[derive(Default)]
+pub struct SparseSets {
+    sets: HashMap<TypeId, SparseSet>,
+}
+
+// Access to the components is controlled by the borrow counters
+unsafe impl Send for SparseSets {}
+unsafe impl Sync for SparseSets {}
+
+impl SparseSets {
+    pub fn register<T: Component>(&mut self) {
+        self.sets.entry(TypeId::of::<T>()).or_insert_with(SparseSet::new::<T>);
+    }
+
+    /// Drops the sparse set of the component type
+    pub fn unregister(&mut self, key: TypeId) {
+        self.sets.remove(&key);
+    }
+
+    /// Returns true if the component type is stored in a sparse set
+    pub fn has(&self, key: TypeId) -> bool {
+        self.sets.contains_key(&key)
+    }
+
+    /// Returns number of components of the type
+    pub fn len(&self, key: TypeId) -> usize {
+        self.sets.get(&key).map(|set| set.len()).unwrap_or(0)
+    }
+
+    /// Returns true if the entity has the component of the sparse type
+    pub fn contains(&self, key: TypeId, entity: Entity) -> bool {
+        self.sets.get(&key).map(|set| set.contains(entity)).unwrap_or(false)
+    }
+
+    /// Returns types of sparse components of the entity
+    pub fn keys(&self, entity: Entity) -> Vec<TypeId> {
+        self.sets
+            .iter()
+            .filter(|(_, set)| set.contains(entity))
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    /// Inserts the component, if its type is stored in a sparse set, otherwise returns it back
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> Result<Option<T>, T> {
+        match self.sets.get_mut(&TypeId::of::<T>()) {
+            Some(set) => Ok(set.insert(entity, component)),
+            None => Err(component),
+        }
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        self.sets.get_mut(&TypeId::of::<T>())?.remove(entity)
+    }
+
+    /// Drops all sparse components of the entity
+    pub fn despawn(&mut self, entity: Entity) {
+        for set in self.sets.values_mut() {
+            set.discard(entity);
+        }
+    }
+
+    /// Borrows the sparse set for reading the component of the entity
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        self.sets.get(&TypeId::of::<T>())?.get(entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        self.sets.get_mut(&TypeId::of::<T>())?.get_mut(entity)
+    }
+
+    /// Borrows the sparse set for reading components of the entities
+    pub fn iter<'w, T: Component>(&'w self, entities: &'w [Entity]) -> Option<SparseIter<'w, T>> {
+        let set = self.sets.get(&TypeId::of::<T>())?;
+        let (dense, borrow) = set.dense.read();
+        Some(SparseIter {
+            set,
+            dense: dense.as_any().downcast_ref().expect("Sparse set must be of the component type"),
+            entities: entities.iter(),
+            _borrow: borrow,
+        })
+    }
+
+    /// Borrows the sparse set for writing components of the entities
+    pub fn iter_mut<'w, T: Component>(
+        &'w self,
+        entities: &'w [Entity],
+    ) -> Option<SparseIterMut<'w, T>> {
+        let set = self.sets.get(&TypeId::of::<T>())?;
+        let (dense, borrow) = set.dense.write();
+        Some(SparseIterMut {
+            set,
+            dense: dense
+                .as_any_mut()
+                .downcast_mut()
+                .expect("Sparse set must be of the component type"),
+            entities: entities.iter(),
+            _borrow: borrow,
+        })
+    }
+}
+
+/// Iterator over components of the entities in a sparse set, yields `None` for entities without
+/// the component
+pub struct SparseIter<'w, T> {
+    set: &'w SparseSet,
+    dense: &'w Vec<T>,
+    entities: std::slice::Iter<'w, Entity>,
+    _borrow: Borrow<'w>,
+}
+
+impl<'w, T> Iterator for SparseIter<'w, T> {
+    type Item = Option<&'w T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (set, dense) = (self.set, self.dense);
+        self.entities.next().map(|entity| set.position(*entity).map(|position| &dense[position]))
+    }
+}
+
+/// Iterator over mutable components of the entities in a sparse set, yields `None` for entities
+/// without the component
+pub struct SparseIterMut<'w, T> {
+    set: &'w SparseSet,
+    dense: &'w mut Vec<T>,
+    entities: std::slice::Iter<'w, Entity>,
+    _borrow: Borrow<'w>,
+}
+
+impl<'w, T> Iterator for SparseIterMut<'w, T> {
+    type Item = Option<&'w mut T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entity = self.entities.next()?;
+        let component = self.set
+            .position(*entity)
+            .map(|position| &mut self.dense[position] as *mut T);
+        // entities of a container are unique, so each component is borrowed only once
+        Some(component.map(|component| unsafe { &mut *component }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SparseSet;
+    use crate::ecs::Entity;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut set = SparseSet::new::<usize>();
+        let entities = (0..4).map(|i| Entity::new(i, 0)).collect::<Vec<_>>();
+        for (i, entity) in entities.iter().enumerate() {
+            assert!(set.insert(*entity, i).is_none());
+        }
+        assert_eq!(set.insert(entities[1], 10usize), Some(1));
+        assert_eq!(set.remove::<usize>(entities[0]), Some(0));
+        assert_eq!(set.remove::<usize>(entities[0]), None);
+        assert_eq!(set.get::<usize>(entities[3]).map(|c| *c), Some(3));
+        assert_eq!(set.get::<usize>(entities[1]).map(|c| *c), Some(10));
+        // stale handle of a reused slot
+        assert!(set.get::<usize>(Entity::new(3, 1)).is_none());
+        assert!(set.discard(entities[2]));
+        assert!(!set.contains(entities[2]));
+        assert_eq!(set.get::<usize>(entities[3]).map(|c| *c), Some(3));
+    }
+}
@@ -1,5 +1,8 @@
 use dotrix_core::{
+    Application,
+    Extension,
     assets::{ Id, Texture },
+    ecs::{ Mut, RunLevel, System },
     input::{
         Button,
         Event as InputEvent,
@@ -8,7 +11,8 @@ use dotrix_core::{
         State as InputState
     },
     renderer::{ WidgetVertex, OverlayProvider, Widget },
-    services::{ Assets, Input },
+    services::{ Assets, Input, Renderer },
+    systems::overlay_update,
 };
 
 pub use egui::*;
@@ -147,6 +151,25 @@ impl OverlayProvider for Egui {
     }
 }
 
+/// Extension adding the `Egui` overlay to the renderer and the `overlay_update` system
+///
+/// Application must have `Assets` and `Input` services.
+pub struct EguiExtension;
+
+impl Extension for EguiExtension {
+    fn add_to(self, app: &mut Application) {
+        app.add_system(System::from(add_overlay).with(RunLevel::Startup));
+        app.add_system(System::from(overlay_update));
+    }
+}
+
+fn add_overlay(renderer: Option<Mut<Renderer>>) {
+    // Headless applications have no renderer
+    if let Some(mut renderer) = renderer {
+        renderer.add_overlay(Box::new(Egui::default()));
+    }
+}
+
 fn egui_texture_to_rgba(texture: &egui::Texture) -> Vec<u8> {
     let mut data = Vec::with_capacity(4 * texture.pixels.len());
 
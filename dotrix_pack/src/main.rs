@@ -0,0 +1,60 @@
+//! Builds a pack of game assets, that could be mounted by `Assets::mount`
+//!
+//! Usage: `dotrix_pack [--compress] <output> <directory>...`
+//!
+//! Files of all directories are packed under paths relative to their directory, files of later
+//! directories replace files of earlier ones under the same paths.
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    process,
+};
+
+use dotrix_core::assets::{Pack, PackBuilder};
+
+const USAGE: &str = "Usage: dotrix_pack [--compress] <output> <directory>...";
+
+fn main() {
+    let mut compression = false;
+    let mut paths = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--compress" | "-c" => compression = true,
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ => paths.push(arg),
+        }
+    }
+
+    if paths.len() < 2 {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    }
+    let output = paths.remove(0);
+
+    let mut builder = PackBuilder::new().compression(compression);
+    for directory in paths.iter() {
+        if let Err(e) = builder.add_directory(directory) {
+            eprintln!("Can't read directory `{}`: {}", directory, e);
+            process::exit(1);
+        }
+    }
+
+    let result = File::create(&output)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            builder.write(&mut writer)?;
+            writer.flush()
+        })
+        .and_then(|_| Pack::open(&output));
+    match result {
+        Ok(pack) => println!("Packed {} files to `{}`", pack.files().count(), output),
+        Err(e) => {
+            eprintln!("Can't write pack `{}`: {}", output, e);
+            process::exit(1);
+        },
+    }
+}
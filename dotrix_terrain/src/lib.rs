@@ -1,7 +1,9 @@
 mod plain;
 mod height_map;
 mod marching_cubes;
+mod terrain;
 
 pub use plain::*;
 pub use height_map::*;
 pub use marching_cubes::*;
+pub use terrain::*;
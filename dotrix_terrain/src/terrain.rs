@@ -0,0 +1,163 @@
+use dotrix_core::{
+    Application,
+    Extension,
+    assets::{ Id, Mesh, Texture },
+    components::Model,
+    ecs::{ Entity, Mut, System },
+    renderer::Transform,
+    services::{ Assets, World },
+};
+
+use crate::{ height_map::HeightMap, plain::Plain };
+
+const MESH_NAME: &str = "terrain::mesh";
+
+/// Service holding the terrain built from a height map by the `terrain_update` system
+pub struct Terrain {
+    /// Texture of the terrain, it must be set to render the terrain
+    pub texture: Id<Texture>,
+    /// Transformation of the terrain model, applied when the terrain is spawned
+    pub transform: Transform,
+    height_map: Option<HeightMap>,
+    mesh: Option<Id<Mesh>>,
+    entity: Option<Entity>,
+}
+
+impl Terrain {
+    pub fn new() -> Self {
+        Self {
+            texture: Id::default(),
+            transform: Transform::default(),
+            height_map: None,
+            mesh: None,
+            entity: None,
+        }
+    }
+
+    /// Requests the terrain mesh to be rebuilt from the height map on the next frame
+    pub fn set_height_map(&mut self, height_map: HeightMap) {
+        self.height_map = Some(height_map);
+    }
+
+    /// Returns id of the terrain mesh, if it was built
+    pub fn mesh(&self) -> Option<Id<Mesh>> {
+        self.mesh
+    }
+
+    /// Returns the entity of the terrain model, if it was spawned
+    pub fn entity(&self) -> Option<Entity> {
+        self.entity
+    }
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// System building the terrain mesh from the height map and spawning the terrain model
+pub fn terrain_update(mut terrain: Mut<Terrain>, mut assets: Mut<Assets>, mut world: Mut<World>) {
+    let height_map = match terrain.height_map.take() {
+        Some(height_map) => height_map,
+        None => return,
+    };
+
+    let positions = Plain::from_height_map(&height_map).positions;
+    let size = height_map.size() as f32;
+    let uvs = positions.iter().map(|[x, _, z]| [x / size, z / size]).collect::<Vec<_>>();
+
+    if let Some(mesh) = terrain.mesh.and_then(|mesh| assets.get_mut(mesh)) {
+        mesh.positions = positions;
+        mesh.uvs = Some(uvs);
+        mesh.normals.take();
+        mesh.calculate();
+        mesh.unload();
+        return;
+    }
+
+    let mut mesh = Mesh {
+        positions,
+        uvs: Some(uvs),
+        ..Default::default()
+    };
+    mesh.calculate();
+    let mesh = assets.store(mesh, MESH_NAME);
+
+    let transform = Transform {
+        translate: terrain.transform.translate,
+        rotate: terrain.transform.rotate,
+        scale: terrain.transform.scale,
+    };
+    let model = Model {
+        mesh,
+        texture: terrain.texture,
+        transform,
+        ..Default::default()
+    };
+    terrain.mesh = Some(mesh);
+    terrain.entity = world.spawn(Some((model,))).into_iter().next();
+}
+
+/// Extension adding the `Terrain` service and the `terrain_update` system
+///
+/// Application must have `Assets` and `World` services.
+pub struct TerrainExtension;
+
+impl Extension for TerrainExtension {
+    fn add_to(self, app: &mut Application) {
+        app.add_default_service(Terrain::new());
+        app.add_system(System::from(terrain_update));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dotrix_core::{
+        Application,
+        components::Model,
+        services::{ Assets, World },
+    };
+
+    use crate::HeightMap;
+    use super::{ Terrain, TerrainExtension };
+
+    fn application() -> Application {
+        let mut app = Application::new("Terrain");
+        app.add_service(Assets::new());
+        app.add_service(World::new());
+        app
+    }
+
+    #[test]
+    fn extension() {
+        let mut app = application();
+        app.add_extension(TerrainExtension);
+        app.service::<Terrain>().set_height_map(HeightMap::new(4));
+
+        let mut app = app.headless();
+        app.step(1);
+
+        let terrain = app.service::<Terrain>().unwrap();
+        assert!(terrain.mesh().is_some());
+        let entity = terrain.entity().expect("Terrain must be spawned");
+        assert!(app.service::<World>().unwrap().get::<Model>(entity).is_some());
+    }
+
+    #[test]
+    fn user_terrain() {
+        let mut app = application();
+        let mut terrain = Terrain::new();
+        terrain.transform.scale.y = 2.0;
+        terrain.set_height_map(HeightMap::new(4));
+        app.add_service(terrain);
+        app.add_extension(TerrainExtension);
+
+        let mut app = app.headless();
+        app.step(1);
+
+        let terrain = app.service::<Terrain>().unwrap();
+        assert!((terrain.transform.scale.y - 2.0).abs() < f32::EPSILON);
+        assert!(terrain.entity().is_some());
+    }
+}
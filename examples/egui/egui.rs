@@ -4,11 +4,12 @@ mod fox;
 use dotrix::{
     Dotrix,
     ecs::{ Mut, RunLevel, System },
+    egui::EguiExtension,
     input::{ Mapper },
     math::{ Point3 },
-    renderer::{ Light },
-    services::{ Assets, Camera, Frame, Input, World },
-    systems::{ overlay_update, skeletal_animation, world_renderer },
+    renderer::{ DefaultRenderer, Light },
+    services::{ Camera, Input, World },
+    systems::{ skeletal_animation },
 };
 use settings::{ Settings };
 
@@ -17,18 +18,15 @@ pub enum Action {}
 
 fn main() {
     Dotrix::application("egui Example")
+        .with(DefaultRenderer)
+        .with(EguiExtension)
         .with_system(System::from(spawn_lights).with(RunLevel::Startup))
         .with_system(System::from(fox::startup).with(RunLevel::Startup))
         .with_system(System::from(skeletal_animation))
-        .with_system(System::from(settings::startup).with(RunLevel::Startup))
-        .with_system(System::from(overlay_update))
         .with_system(System::from(settings::ui))
         .with_system(System::from(settings::update_camera))
         .with_system(System::from(settings::update_fox))
         .with_system(System::from(settings::update_lights))
-        .with_system(System::from(world_renderer).with(RunLevel::Render))
-        .with_service(Assets::new())
-        .with_service(Frame::new())
         .with_service(Settings::new())
         .with_service(Camera {
             distance: 222.0,
@@ -37,7 +35,6 @@ fn main() {
             target: Point3::new(0.0, 0.5, 0.0),
             ..Default::default()
         })
-        .with_service(World::new())
         .with_service(Input::new(Box::new(Mapper::<Action>::new())))
         .run();
 }
@@ -199,10 +199,6 @@ pub fn ui(mut editor: Mut<Settings>, renderer: Mut<Renderer>) {
     });
 }
 
-pub fn startup(mut renderer: Mut<Renderer>) {
-    renderer.add_overlay(Box::new(Egui::default()));
-}
-
 /// This func updates camera based on values in editor and controls
 pub fn update_camera(mut camera: Mut<Camera>, mut editor: Mut<Settings>, input: Const<Input>, frame: Const<Frame>) {
     const ROTATE_SPEED: f32 = PI / 10.0;